@@ -0,0 +1,130 @@
+//! Derive macro for [`prometheus-macros`](https://docs.rs/prometheus-macros).
+//!
+//! This crate is re-exported by `prometheus-macros` with the `derive` feature and should
+//! not be used directly.
+
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, LitStr};
+
+/// Derives the `register` method and the metric accessors for a struct of metrics.
+///
+/// See the `prometheus-macros` documentation of `CompositeMetric` for details.
+#[proc_macro_derive(CompositeMetric, attributes(metric))]
+pub fn derive_composite_metric(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The options of a `#[metric(...)]` field attribute.
+struct MetricAttr {
+    name: LitStr,
+    desc: LitStr,
+    labels: Option<Expr>,
+    buckets: Option<Expr>,
+}
+
+impl MetricAttr {
+    fn from_field(field: &syn::Field) -> syn::Result<Self> {
+        let attr = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("metric"))
+            .ok_or_else(|| syn::Error::new_spanned(field, "missing `#[metric(...)]` attribute"))?;
+
+        let (mut name, mut desc, mut labels, mut buckets) = (None, None, None, None);
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("desc") {
+                desc = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("labels") {
+                labels = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("buckets") {
+                buckets = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unknown metric option"));
+            }
+            Ok(())
+        })?;
+
+        Ok(Self {
+            name: name.ok_or_else(|| syn::Error::new_spanned(attr, "missing `name` option"))?,
+            desc: desc.ok_or_else(|| syn::Error::new_spanned(attr, "missing `desc` option"))?,
+            labels,
+            buckets,
+        })
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input,
+                    "`CompositeMetric` requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input,
+                "`CompositeMetric` can only be derived for structs",
+            ))
+        }
+    };
+
+    let vis = &input.vis;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut inits = Vec::with_capacity(fields.len());
+    let mut accessors = Vec::with_capacity(fields.len());
+    let mut names = Vec::with_capacity(fields.len());
+    for field in fields {
+        let attr = MetricAttr::from_field(field)?;
+        let field_name = field.ident.as_ref().expect("named field");
+        let field_ty = &field.ty;
+        let (name, desc) = (&attr.name, &attr.desc);
+        let labels = attr.labels.iter();
+        let buckets = attr.buckets.iter();
+
+        inits.push(quote! {
+            let opts = ::prometheus_macros::Opts::new(#name, #desc);
+            #(let opts = opts.with_variable_labels(&(#labels)[..]);)*
+            #(let opts = opts.with_buckets(&#buckets);)*
+            let #field_name: #field_ty = ::std::convert::TryInto::try_into(opts)?;
+            ::prometheus_macros::Registrar::register_collector(
+                registry,
+                ::std::boxed::Box::new(::std::clone::Clone::clone(&#field_name)),
+            )?;
+        });
+        accessors.push(quote! {
+            #vis fn #field_name(&self) -> &#field_ty {
+                &self.#field_name
+            }
+        });
+        names.push(field_name);
+    }
+
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Constructs all metrics and registers them to the given registry.
+            #vis fn register(
+                registry: &impl ::prometheus_macros::Registrar,
+            ) -> ::prometheus::Result<Self> {
+                #(#inits)*
+                Ok(Self { #(#names),* })
+            }
+
+            #(#accessors)*
+        }
+    })
+}
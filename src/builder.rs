@@ -0,0 +1,142 @@
+use prometheus::Registry;
+
+/// Assembles several composite metrics into a single registry.
+///
+/// The registrations added with [`add`](Self::add) run in order on [`build`](Self::build),
+/// which returns the registry along with a tuple of the registered metrics.
+///
+/// ```
+/// use prometheus::{Counter, IntGauge};
+/// use prometheus_macros::{composite_metric, RegistryBuilder};
+///
+/// composite_metric! {
+///     struct HttpMetrics {
+///         #[name = "http_requests"]
+///         #[desc = "Number of HTTP requests"]
+///         requests: Counter,
+///     }
+/// }
+///
+/// composite_metric! {
+///     struct PoolMetrics {
+///         #[name = "pool_connections"]
+///         #[desc = "Number of pooled connections"]
+///         connections: IntGauge,
+///     }
+/// }
+///
+/// let (registry, (http, pool)) = RegistryBuilder::new()
+///     .add(HttpMetrics::register)
+///     .add(PoolMetrics::register)
+///     .build()
+///     .unwrap();
+/// http.requests().inc();
+/// pool.connections().set(4);
+/// assert_eq!(registry.gather().len(), 2);
+/// ```
+pub struct RegistryBuilder<F = fn(&Registry) -> prometheus::Result<()>> {
+    registry: Registry,
+    register: F,
+}
+
+impl RegistryBuilder {
+    /// Creates a builder registering to a new registry.
+    pub fn new() -> Self {
+        Self::with_registry(Registry::new())
+    }
+
+    /// Creates a builder registering to the given registry, e.g. one with a prefix.
+    pub fn with_registry(registry: Registry) -> Self {
+        Self {
+            registry,
+            register: |_| Ok(()),
+        }
+    }
+}
+
+impl Default for RegistryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TupleAppend, F: FnOnce(&Registry) -> prometheus::Result<T>> RegistryBuilder<F> {
+    /// Adds a registration, e.g. the `register` method of a composite metric.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<U>(
+        self,
+        register: impl FnOnce(&Registry) -> prometheus::Result<U>,
+    ) -> RegistryBuilder<impl FnOnce(&Registry) -> prometheus::Result<T::Output<U>>> {
+        let previous = self.register;
+        RegistryBuilder {
+            registry: self.registry,
+            register: move |registry: &Registry| {
+                let metrics = previous(registry)?;
+                Ok(metrics.append(register(registry)?))
+            },
+        }
+    }
+
+    /// Runs all registrations, returning the registry and the registered metrics.
+    ///
+    /// Stops at the first failed registration.
+    pub fn build(self) -> prometheus::Result<(Registry, T)> {
+        let metrics = (self.register)(&self.registry)?;
+        Ok((self.registry, metrics))
+    }
+}
+
+/// Tuples that can be extended by another element.
+pub trait TupleAppend {
+    /// The tuple with `U` appended.
+    type Output<U>;
+
+    /// Appends `value` to the tuple.
+    fn append<U>(self, value: U) -> Self::Output<U>;
+}
+
+macro_rules! impl_tuple_append {
+    ($($ty:ident),*) => {
+        impl<$($ty),*> TupleAppend for ($($ty,)*) {
+            type Output<U> = ($($ty,)* U,);
+
+            #[allow(non_snake_case)]
+            fn append<U>(self, value: U) -> Self::Output<U> {
+                let ($($ty,)*) = self;
+                ($($ty,)* value,)
+            }
+        }
+    };
+}
+
+impl_tuple_append!();
+impl_tuple_append!(A);
+impl_tuple_append!(A, B);
+impl_tuple_append!(A, B, C);
+impl_tuple_append!(A, B, C, D);
+impl_tuple_append!(A, B, C, D, E);
+impl_tuple_append!(A, B, C, D, E, G);
+impl_tuple_append!(A, B, C, D, E, G, H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{Gauge, IntCounter};
+
+    #[test]
+    fn build_stops_at_failed_registration() {
+        let result = RegistryBuilder::new()
+            .add(|registry| {
+                let counter = IntCounter::new("builder_counter", "description")?;
+                registry.register(Box::new(counter.clone()))?;
+                Ok(counter)
+            })
+            .add(|registry| {
+                let gauge = Gauge::new("builder_counter", "description")?;
+                registry.register(Box::new(gauge.clone()))?;
+                Ok(gauge)
+            })
+            .build();
+        assert!(result.is_err());
+    }
+}
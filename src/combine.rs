@@ -0,0 +1,83 @@
+use std::iter::Chain;
+
+use prometheus::core::Collector;
+
+/// Two composite metrics combined into one logical metric set.
+///
+/// Structs annotated with `#[combine]` construct it with `merge`, e.g. to join core metrics
+/// registered at startup with optional ones registered later. Further sets are merged into
+/// nested combinations. `&Combined` iterates the collectors of both parts.
+///
+/// ```
+/// use prometheus::{Counter, IntGauge, Registry};
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     #[combine]
+///     struct CoreMetrics {
+///         #[name = "core_requests"]
+///         #[desc = "Number of requests"]
+///         requests: Counter,
+///     }
+/// }
+///
+/// composite_metric! {
+///     struct CacheMetrics {
+///         #[name = "cache_entries"]
+///         #[desc = "Number of cached entries"]
+///         entries: IntGauge,
+///     }
+/// }
+///
+/// let registry = Registry::new();
+/// let core = CoreMetrics::register(&registry).unwrap();
+/// let metrics = core.merge(CacheMetrics::register(&registry).unwrap());
+/// metrics.first().requests().inc();
+/// metrics.second().entries().set(3);
+/// assert_eq!(metrics.into_iter().count(), 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Combined<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Combined<A, B> {
+    /// Combines two metric sets.
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+
+    /// Returns the first metric set.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Returns the second metric set.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    /// Splits the combination into its metric sets.
+    pub fn into_parts(self) -> (A, B) {
+        (self.first, self.second)
+    }
+
+    /// Combines this combination with another metric set.
+    pub fn merge<C>(self, other: C) -> Combined<Self, C> {
+        Combined::new(self, other)
+    }
+}
+
+impl<'a, A, B> IntoIterator for &'a Combined<A, B>
+where
+    &'a A: IntoIterator<Item = Box<dyn Collector>>,
+    &'a B: IntoIterator<Item = Box<dyn Collector>>,
+{
+    type Item = Box<dyn Collector>;
+    type IntoIter = Chain<<&'a A as IntoIterator>::IntoIter, <&'a B as IntoIterator>::IntoIter>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.first.into_iter().chain(&self.second)
+    }
+}
@@ -0,0 +1,120 @@
+/// Builds a `&'static str` at compile time from string literals and unsigned integer
+/// constants, e.g. const generic parameters.
+///
+/// This is the counterpart of [`concat!`] for metric names of const-generic composite metrics,
+/// as `concat!` only accepts literals. The name is limited to 255 bytes.
+///
+/// ```
+/// use prometheus_macros::const_name;
+///
+/// fn shard_name<const N: usize>() -> &'static str {
+///     const_name!("shard_", N, "_requests")
+/// }
+///
+/// assert_eq!(shard_name::<3>(), "shard_3_requests");
+/// ```
+#[macro_export]
+macro_rules! const_name {
+    (@push $name:expr, $part:literal $(, $($rest:tt)*)?) => {
+        $crate::const_name!(@push $name.push_str($part), $($($rest)*)?)
+    };
+    (@push $name:expr, $part:expr $(, $($rest:tt)*)?) => {
+        $crate::const_name!(@push $name.push_num($part as u128), $($($rest)*)?)
+    };
+    (@push $name:expr, $(,)?) => {
+        $name
+    };
+    ($($part:tt)+) => {
+        const { $crate::const_name!(@push $crate::__private::ConstName::new(), $($part)+) }
+            .as_str()
+    };
+}
+
+/// A fixed-capacity string built in const contexts by [`const_name!`].
+#[derive(Clone, Copy, Debug)]
+pub struct ConstName {
+    buf: [u8; 255],
+    len: usize,
+}
+
+impl ConstName {
+    /// Creates an empty name.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; 255],
+            len: 0,
+        }
+    }
+
+    /// Appends a string.
+    pub const fn push_str(mut self, s: &str) -> Self {
+        let bytes = s.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            self.buf[self.len] = bytes[i];
+            self.len += 1;
+            i += 1;
+        }
+        self
+    }
+
+    /// Appends a dotted name like `http.server.requests`, replacing the dots with underscores.
+    ///
+    /// Panics if a segment is empty or the name has characters not allowed in metric names,
+    /// which fails the compilation in const contexts.
+    pub const fn push_dotted(mut self, name: &str) -> Self {
+        let bytes = name.as_bytes();
+        if bytes.is_empty() {
+            panic!("dotted metric name must not be empty");
+        }
+        let mut i = 0;
+        while i < bytes.len() {
+            self.buf[self.len] = match bytes[i] {
+                b'.' if i == 0 || i + 1 == bytes.len() || bytes[i - 1] == b'.' => {
+                    panic!("dotted metric name must not have empty segments")
+                }
+                b'.' => b'_',
+                b'0'..=b'9' if self.len == 0 => panic!("metric name must not start with a digit"),
+                b @ (b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b':') => b,
+                _ => panic!("dotted metric name has an invalid character"),
+            };
+            self.len += 1;
+            i += 1;
+        }
+        self
+    }
+
+    /// Appends the decimal digits of a number.
+    pub const fn push_num(mut self, mut n: u128) -> Self {
+        let mut digits = [0; 39];
+        let mut count = 0;
+        loop {
+            digits[count] = b'0' + (n % 10) as u8;
+            count += 1;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        while count > 0 {
+            count -= 1;
+            self.buf[self.len] = digits[count];
+            self.len += 1;
+        }
+        self
+    }
+
+    /// Returns the name built so far.
+    pub const fn as_str(&self) -> &str {
+        match std::str::from_utf8(self.buf.split_at(self.len).0) {
+            Ok(s) => s,
+            Err(_) => panic!("metric name is not valid UTF-8"),
+        }
+    }
+}
+
+impl Default for ConstName {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{
+    Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec,
+};
+
+use crate::{Opts, RegistryLike};
+
+macro_rules! dyn_metric {
+    ($($kind:ident),+ $(,)?) => {
+        /// The kind of a metric registered with [`register_from_iter`].
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum MetricKind {
+            $(
+                #[doc = concat!("A [`", stringify!($kind), "`].")]
+                $kind,
+            )+
+        }
+
+        /// A type-erased handle of a metric registered with [`register_from_iter`].
+        #[derive(Clone)]
+        pub enum DynMetric {
+            $(
+                #[doc = concat!("A [`", stringify!($kind), "`].")]
+                $kind($kind),
+            )+
+        }
+
+        impl DynMetric {
+            /// Constructs a metric of the given kind.
+            pub fn new(opts: Opts<'_>, kind: MetricKind) -> prometheus::Result<Self> {
+                match kind {
+                    $(MetricKind::$kind => opts.try_into().map(Self::$kind),)+
+                }
+            }
+
+            /// Returns the kind of the metric.
+            pub fn kind(&self) -> MetricKind {
+                match self {
+                    $(Self::$kind(_) => MetricKind::$kind,)+
+                }
+            }
+        }
+
+        impl Collector for DynMetric {
+            fn desc(&self) -> Vec<&Desc> {
+                match self {
+                    $(Self::$kind(metric) => metric.desc(),)+
+                }
+            }
+
+            fn collect(&self) -> Vec<MetricFamily> {
+                match self {
+                    $(Self::$kind(metric) => metric.collect(),)+
+                }
+            }
+        }
+    };
+}
+
+dyn_metric!(
+    Counter,
+    IntCounter,
+    Gauge,
+    IntGauge,
+    Histogram,
+    CounterVec,
+    IntCounterVec,
+    GaugeVec,
+    IntGaugeVec,
+    HistogramVec,
+);
+
+/// Constructs and registers a dynamic set of metrics, e.g. declared by plugins at runtime.
+///
+/// Returns the registered metrics by name. If a metric fails to register, the error is
+/// returned and the metrics registered before remain in the registry.
+///
+/// ```
+/// use prometheus::Registry;
+/// use prometheus_macros::{register_from_iter, DynMetric, MetricKind, Opts};
+///
+/// let registry = Registry::new();
+/// let metrics = register_from_iter(
+///     &registry,
+///     [(Opts::new("plugin_calls", "Plugin calls"), MetricKind::IntCounter)],
+/// )
+/// .unwrap();
+/// if let Some(DynMetric::IntCounter(calls)) = metrics.get("plugin_calls") {
+///     calls.inc();
+/// }
+/// ```
+pub fn register_from_iter<'a>(
+    registry: &impl RegistryLike,
+    metrics: impl IntoIterator<Item = (Opts<'a>, MetricKind)>,
+) -> prometheus::Result<HashMap<String, DynMetric>> {
+    let registry = registry.as_registry();
+    metrics
+        .into_iter()
+        .map(|(opts, kind)| {
+            let name = opts.name.to_string();
+            let metric = DynMetric::new(opts, kind)?;
+            registry.register(Box::new(metric.clone()))?;
+            Ok((name, metric))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn register_mixed_set() {
+        let registry = Registry::new();
+        let metrics = register_from_iter(
+            &registry,
+            [
+                (Opts::new("dyn_counter", "description"), MetricKind::Counter),
+                (
+                    Opts::new("dyn_gauge_vec", "description").with_variable_labels(&["label"]),
+                    MetricKind::GaugeVec,
+                ),
+                (
+                    Opts::new("dyn_hist", "description").with_buckets(&[0.5, 1.0]),
+                    MetricKind::Histogram,
+                ),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(metrics.len(), 3);
+        assert_eq!(metrics["dyn_gauge_vec"].kind(), MetricKind::GaugeVec);
+        match &metrics["dyn_counter"] {
+            DynMetric::Counter(counter) => counter.inc(),
+            _ => panic!("expected a counter"),
+        }
+        assert_eq!(registry.gather().len(), 2);
+
+        let duplicate = register_from_iter(
+            &registry,
+            [(Opts::new("dyn_counter", "description"), MetricKind::Counter)],
+        );
+        assert!(duplicate.is_err());
+    }
+}
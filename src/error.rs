@@ -0,0 +1,96 @@
+use std::fmt;
+
+/// A [`prometheus::Error`] with the context of the failed operation.
+///
+/// Returned by the `try_register` method generated by [`composite_metric!`](crate::composite_metric).
+#[derive(Debug)]
+pub struct Error {
+    operation: Option<&'static str>,
+    metric: Option<String>,
+    source: prometheus::Error,
+}
+
+impl Error {
+    /// Creates an error naming the failed operation and metric.
+    pub fn new(
+        operation: &'static str,
+        metric: impl Into<String>,
+        source: prometheus::Error,
+    ) -> Self {
+        Self {
+            operation: Some(operation),
+            metric: Some(metric.into()),
+            source,
+        }
+    }
+
+    /// Returns the failed operation, e.g. `"register"`.
+    pub fn operation(&self) -> Option<&'static str> {
+        self.operation
+    }
+
+    /// Returns the name of the field of the failed metric.
+    pub fn metric(&self) -> Option<&str> {
+        self.metric.as_deref()
+    }
+
+    /// Returns the underlying prometheus error.
+    pub fn inner(&self) -> &prometheus::Error {
+        &self.source
+    }
+
+    /// Returns the underlying prometheus error, discarding the context.
+    pub fn into_inner(self) -> prometheus::Error {
+        self.source
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.operation, &self.metric) {
+            (Some(operation), Some(metric)) => {
+                write!(
+                    f,
+                    "failed to {operation} metric `{metric}`: {}",
+                    self.source
+                )
+            }
+            (Some(operation), None) => write!(f, "failed to {operation}: {}", self.source),
+            (None, Some(metric)) => write!(f, "metric `{metric}`: {}", self.source),
+            (None, None) => self.source.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<prometheus::Error> for Error {
+    fn from(source: prometheus::Error) -> Self {
+        Self {
+            operation: None,
+            metric: None,
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_with_context() {
+        let err = Error::new("register", "requests", prometheus::Error::AlreadyReg);
+        assert_eq!(
+            err.to_string(),
+            "failed to register metric `requests`: Duplicate metrics collector registration attempted"
+        );
+        let err = Error::from(prometheus::Error::AlreadyReg);
+        assert_eq!(err.metric(), None);
+        assert!(matches!(err.into_inner(), prometheus::Error::AlreadyReg));
+    }
+}
@@ -0,0 +1,44 @@
+use prometheus::core::{Atomic, AtomicF64, GenericGauge};
+
+/// Increments a gauge on creation and decrements it again when dropped, e.g. to track
+/// in-flight requests.
+///
+/// Guards are returned by the `<field>_guard` methods generated for gauge fields.
+///
+/// ```
+/// use prometheus::{IntGauge, Registry};
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "in_flight_requests"]
+///         #[desc = "Number of requests being served"]
+///         in_flight: IntGauge,
+///     }
+/// }
+///
+/// let metric = CompositeMetric::register(&Registry::new()).unwrap();
+/// {
+///     let _guard = metric.in_flight_guard();
+///     assert_eq!(metric.in_flight().get(), 1);
+/// }
+/// assert_eq!(metric.in_flight().get(), 0);
+/// ```
+#[must_use = "the gauge is decremented as soon as the guard is dropped"]
+pub struct InFlightGuard<P: Atomic = AtomicF64> {
+    gauge: GenericGauge<P>,
+}
+
+impl<P: Atomic> InFlightGuard<P> {
+    /// Increments the given gauge and returns a guard decrementing it on drop.
+    pub fn new(gauge: GenericGauge<P>) -> Self {
+        gauge.inc();
+        Self { gauge }
+    }
+}
+
+impl<P: Atomic> Drop for InFlightGuard<P> {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
@@ -0,0 +1,91 @@
+use prometheus::core::Collector;
+use prometheus::proto::MetricType;
+
+/// A snapshot of the counter values of a composite metric, used to detect stalled counters.
+///
+/// Snapshots are taken with the `counter_snapshot` method generated for structs annotated
+/// with `#[counter_health]`. Vector counters are summed over all label values.
+///
+/// ```
+/// use prometheus::{Counter, Registry};
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     #[counter_health]
+///     struct CompositeMetric {
+///         #[name = "processed_jobs"]
+///         #[desc = "Number of processed jobs"]
+///         processed: Counter,
+///     }
+/// }
+///
+/// let metric = CompositeMetric::register(&Registry::new()).unwrap();
+/// let before = metric.counter_snapshot();
+/// metric.processed().inc();
+/// assert_eq!(before.advanced(&metric.counter_snapshot()), ["processed"]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CounterSnapshot {
+    counters: Vec<(&'static str, f64)>,
+}
+
+impl CounterSnapshot {
+    /// Returns the value of the counter field with the given name.
+    pub fn get(&self, field: &str) -> Option<f64> {
+        self.counters
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, value)| *value)
+    }
+
+    /// Returns the counter fields that increased between this and a later snapshot.
+    pub fn advanced(&self, later: &Self) -> Vec<&'static str> {
+        self.compare(later, |before, after| after > before)
+    }
+
+    /// Returns the counter fields that didn't change between this and a later snapshot.
+    pub fn stalled(&self, later: &Self) -> Vec<&'static str> {
+        self.compare(later, |before, after| after <= before)
+    }
+
+    fn compare(&self, later: &Self, f: impl Fn(f64, f64) -> bool) -> Vec<&'static str> {
+        self.counters
+            .iter()
+            .filter(|(name, before)| later.get(name).is_some_and(|after| f(*before, after)))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl FromIterator<(&'static str, f64)> for CounterSnapshot {
+    fn from_iter<T: IntoIterator<Item = (&'static str, f64)>>(iter: T) -> Self {
+        Self {
+            counters: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Sums the values of all counter series of a collector.
+pub fn counter_total(collector: &dyn Collector) -> f64 {
+    collector
+        .collect()
+        .iter()
+        .filter(|family| family.get_field_type() == MetricType::COUNTER)
+        .flat_map(|family| family.get_metric())
+        .map(|metric| metric.get_counter().value())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{IntCounterVec, Opts};
+
+    #[test]
+    fn counter_total_sums_series() {
+        let counter = IntCounterVec::new(Opts::new("requests", "description"), &["path"]).unwrap();
+        counter.with_label_values(&["/a"]).inc_by(2);
+        counter.with_label_values(&["/b"]).inc();
+        assert_eq!(counter_total(&counter), 3.0);
+    }
+}
@@ -0,0 +1,117 @@
+use prometheus::proto::{MetricFamily, MetricType};
+
+/// Converts the cumulative buckets of a gathered histogram family into per-bucket counts.
+///
+/// Returns `(upper_bound, count)` pairs where each count only includes the observations
+/// above the previous bound, ending with the `+Inf` bucket. Series of a histogram vec are
+/// summed up. Families of other types yield no buckets.
+///
+/// ```
+/// use prometheus::core::Collector;
+/// use prometheus::{Histogram, HistogramOpts};
+///
+/// let hist = Histogram::with_opts(HistogramOpts::new("latency", "Latency").buckets(vec![0.1, 1.0]))
+///     .unwrap();
+/// hist.observe(0.05);
+/// hist.observe(0.5);
+/// let deltas = prometheus_macros::histogram_deltas(&hist.collect()[0]);
+/// assert_eq!(deltas, [(0.1, 1), (1.0, 1), (f64::INFINITY, 0)]);
+/// ```
+pub fn histogram_deltas(family: &MetricFamily) -> Vec<(f64, u64)> {
+    let mut previous = 0;
+    cumulative_buckets(family)
+        .into_iter()
+        .map(|(bound, count)| {
+            let delta = count.saturating_sub(previous);
+            previous = count;
+            (bound, delta)
+        })
+        .collect()
+}
+
+/// Returns the cumulative `(upper_bound, count)` buckets of a gathered histogram family,
+/// ending with the `+Inf` bucket. Series of a histogram vec are summed up.
+pub fn cumulative_buckets(family: &MetricFamily) -> Vec<(f64, u64)> {
+    if family.get_field_type() != MetricType::HISTOGRAM {
+        return Vec::new();
+    }
+
+    let mut cumulative: Vec<(f64, u64)> = Vec::new();
+    let mut total = 0;
+    for metric in family.get_metric() {
+        let histogram = metric.get_histogram();
+        for (i, bucket) in histogram.get_bucket().iter().enumerate() {
+            match cumulative.get_mut(i) {
+                Some((_, count)) => *count += bucket.cumulative_count(),
+                None => cumulative.push((bucket.upper_bound(), bucket.cumulative_count())),
+            }
+        }
+        total += histogram.get_sample_count();
+    }
+    cumulative.push((f64::INFINITY, total));
+    cumulative
+}
+
+/// Computes the fraction of observations within each bucket boundary of a gathered histogram
+/// family, e.g. to check SLO latency targets.
+///
+/// Returns `(upper_bound, fraction)` pairs for the finite bounds. Series of a histogram vec
+/// are summed up. A histogram without observations yields fractions of `0.0`, and families of
+/// other types yield no bounds.
+///
+/// ```
+/// use prometheus::core::Collector;
+/// use prometheus::{Histogram, HistogramOpts};
+///
+/// let hist = Histogram::with_opts(HistogramOpts::new("latency", "Latency").buckets(vec![0.3, 1.0]))
+///     .unwrap();
+/// for v in [0.1, 0.2, 0.5, 2.0] {
+///     hist.observe(v);
+/// }
+/// let fractions = prometheus_macros::slo_fractions(&hist.collect()[0]);
+/// assert_eq!(fractions, [(0.3, 0.5), (1.0, 0.75)]);
+/// ```
+pub fn slo_fractions(family: &MetricFamily) -> Vec<(f64, f64)> {
+    let deltas = histogram_deltas(family);
+    let total: u64 = deltas.iter().map(|(_, count)| count).sum();
+
+    let mut within = 0;
+    deltas
+        .into_iter()
+        .filter(|(bound, _)| bound.is_finite())
+        .map(|(bound, count)| {
+            within += count;
+            let fraction = if total == 0 {
+                0.0
+            } else {
+                within as f64 / total as f64
+            };
+            (bound, fraction)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::core::Collector;
+    use prometheus::{HistogramOpts, HistogramVec};
+
+    #[test]
+    fn deltas_of_histogram_vec() {
+        let hist = HistogramVec::new(
+            HistogramOpts::new("example_hist", "description").buckets(vec![0.1, 0.5, 1.0]),
+            &["label"],
+        )
+        .unwrap();
+        for v in [0.05, 0.2, 0.3, 2.0] {
+            hist.with_label_values(&["a"]).observe(v);
+        }
+        hist.with_label_values(&["b"]).observe(0.7);
+
+        assert_eq!(
+            histogram_deltas(&hist.collect()[0]),
+            [(0.1, 1), (0.5, 2), (1.0, 1), (f64::INFINITY, 1)]
+        );
+    }
+}
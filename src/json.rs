@@ -0,0 +1,189 @@
+use prometheus::proto::{Metric, MetricFamily, MetricType};
+use prometheus::Registry;
+use serde_json::{json, Map, Value};
+
+/// Converts the metrics gathered from `registry` into JSON, e.g. for a debug endpoint.
+///
+/// The result is an object keyed by the family name, each with its `type`, `help` and a
+/// `series` array holding the `labels` and the value of every series. Counters and gauges
+/// have a `value`, histograms a `count`, `sum` and their cumulative `buckets` keyed by the
+/// upper bound, including `+Inf`.
+///
+/// ```
+/// use prometheus::{IntCounter, Registry};
+///
+/// let registry = Registry::new();
+/// let counter = IntCounter::new("requests", "Requests").unwrap();
+/// registry.register(Box::new(counter.clone())).unwrap();
+/// counter.inc();
+///
+/// let json = prometheus_macros::gather_json(&registry);
+/// assert_eq!(json["requests"]["series"][0]["value"], 1.0);
+/// ```
+pub fn gather_json(registry: &Registry) -> Value {
+    registry
+        .gather()
+        .iter()
+        .map(|family| (family.name().to_owned(), family_json(family)))
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+fn family_json(family: &MetricFamily) -> Value {
+    let kind = family.get_field_type();
+    let series = family
+        .get_metric()
+        .iter()
+        .map(|metric| series_json(kind, metric))
+        .collect::<Vec<_>>();
+    json!({
+        "type": format!("{kind:?}").to_lowercase(),
+        "help": family.help(),
+        "series": series,
+    })
+}
+
+fn series_json(kind: MetricType, metric: &Metric) -> Value {
+    let labels = metric
+        .get_label()
+        .iter()
+        .map(|l| (l.name().to_owned(), l.value().into()))
+        .collect::<Map<_, _>>();
+    match kind {
+        MetricType::COUNTER => json!({ "labels": labels, "value": metric.get_counter().value() }),
+        MetricType::GAUGE => json!({ "labels": labels, "value": metric.get_gauge().value() }),
+        MetricType::HISTOGRAM => {
+            let histogram = metric.get_histogram();
+            let mut buckets = histogram
+                .get_bucket()
+                .iter()
+                .map(|b| (b.upper_bound().to_string(), b.cumulative_count().into()))
+                .collect::<Map<_, _>>();
+            buckets.insert("+Inf".to_owned(), histogram.get_sample_count().into());
+            json!({
+                "labels": labels,
+                "count": histogram.get_sample_count(),
+                "sum": histogram.get_sample_sum(),
+                "buckets": buckets,
+            })
+        }
+        _ => json!({ "labels": labels }),
+    }
+}
+
+/// Describes a metric in the `dashboard_spec` of a composite metric.
+pub fn metric_spec(name: &str, kind: &str, help: &str, labels: &[&str]) -> Value {
+    json!({ "name": name, "type": kind, "help": help, "labels": labels })
+}
+
+/// Wraps the metric descriptions of `dashboard_spec`.
+pub fn dashboard_spec(metrics: Vec<Value>) -> Value {
+    json!({ "metrics": metrics })
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __composite_metric_json {
+    (@dashboard_spec $opts:tt $v:vis, $([$field_name:ident: $field_ty:tt, $($field_attr:tt)*])+) => {
+        /// Describes the metrics for dashboard generation tooling, from the attributes only.
+        ///
+        /// The result holds a `metrics` array with the `name`, `type`, `help` and `labels` of
+        /// every metric, in declaration order. Placeholders in the help are kept as they are.
+        #[allow(dead_code)]
+        $v fn dashboard_spec() -> $crate::__private::serde_json::Value {
+            let mut metrics = ::std::vec::Vec::with_capacity(Self::FIELD_COUNT);
+            $(
+                $crate::__composite_metric_field!(
+                    @dashboard_spec $opts metrics, $field_name: $field_ty, $($field_attr)*
+                );
+            )+
+            $crate::__private::dashboard_spec(metrics)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{HistogramOpts, HistogramVec, IntCounter};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn dashboard_spec() {
+        use crate::composite_metric;
+        use prometheus::{HistogramVec, IntGauge};
+
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "connections"]
+                #[desc = "Open connections"]
+                connections: IntGauge,
+                #[name = "latency_seconds"]
+                #[desc = "Latency of {service}"]
+                #[labels = ["method", "path"]]
+                latency: HistogramVec,
+            }
+        }
+
+        assert_eq!(
+            CompositeMetric::dashboard_spec(),
+            json!({
+                "metrics": [
+                    {
+                        "name": "connections",
+                        "type": "gauge",
+                        "help": "Open connections",
+                        "labels": [],
+                    },
+                    {
+                        "name": "latency_seconds",
+                        "type": "histogram",
+                        "help": "Latency of {service}",
+                        "labels": ["method", "path"],
+                    },
+                ],
+            })
+        );
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.connections().inc();
+        metric
+            .latency()
+            .with_label_values(&["GET", "/"])
+            .observe(0.1);
+    }
+
+    #[test]
+    fn counter_and_histogram() {
+        let registry = Registry::new();
+        let counter = IntCounter::new("requests_total", "Requests").unwrap();
+        let opts = HistogramOpts::new("latency", "Latency").buckets(vec![0.1, 1.0]);
+        let hist = HistogramVec::new(opts, &["path"]).unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(hist.clone())).unwrap();
+        counter.inc_by(3);
+        hist.with_label_values(&["/"]).observe(0.5);
+        hist.with_label_values(&["/"]).observe(2.0);
+
+        assert_eq!(
+            gather_json(&registry),
+            json!({
+                "requests_total": {
+                    "type": "counter",
+                    "help": "Requests",
+                    "series": [{ "labels": {}, "value": 3.0 }],
+                },
+                "latency": {
+                    "type": "histogram",
+                    "help": "Latency",
+                    "series": [{
+                        "labels": { "path": "/" },
+                        "count": 2,
+                        "sum": 2.5,
+                        "buckets": { "0.1": 0, "1": 1, "+Inf": 2 },
+                    }],
+                },
+            })
+        );
+    }
+}
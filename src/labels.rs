@@ -0,0 +1,170 @@
+/// Enums whose variants are the values of a label, used with `#[labels_from = ...]`.
+///
+/// Implemented with [`label_enum!`](crate::label_enum). A vec metric annotated with
+/// `#[labels_from = Enum]` uses [`LABEL`](LabelEnum::LABEL) as its only label, is
+/// pre-initialized with all [`VALUES`](LabelEnum::VALUES), and gets a typed
+/// `<field>_with(&self, value: Enum)` accessor for the series of a variant.
+///
+/// ```
+/// use prometheus::{IntCounterVec, Registry};
+/// use prometheus_macros::{composite_metric, label_enum};
+///
+/// label_enum! {
+///     #[label = "method"]
+///     pub enum HttpMethod {
+///         Get = "GET",
+///         Post = "POST",
+///     }
+/// }
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "requests"]
+///         #[desc = "Number of requests"]
+///         #[labels_from = HttpMethod]
+///         requests: IntCounterVec,
+///     }
+/// }
+///
+/// let metric = CompositeMetric::register(&Registry::new()).unwrap();
+/// metric.requests_with(HttpMethod::Get).inc();
+/// ```
+pub trait LabelEnum: Copy {
+    /// The name of the label.
+    const LABEL: &'static str;
+    /// The label values of all variants.
+    const VALUES: &'static [&'static str];
+
+    /// Returns the label value of the variant.
+    fn as_label(&self) -> &'static str;
+}
+
+/// Declares a fieldless enum implementing [`LabelEnum`].
+///
+/// The label name is given with `#[label = "..."]`. Variants use their name as label value,
+/// unless a value is given with `Variant = "value"`.
+///
+/// ```
+/// use prometheus_macros::{label_enum, LabelEnum};
+///
+/// label_enum! {
+///     #[label = "status"]
+///     #[derive(PartialOrd, Ord)]
+///     pub enum Status {
+///         Success = "2xx",
+///         Failure = "5xx",
+///         Unknown,
+///     }
+/// }
+///
+/// assert_eq!(Status::VALUES, ["2xx", "5xx", "Unknown"]);
+/// assert_eq!(Status::Failure.as_label(), "5xx");
+/// ```
+#[macro_export]
+macro_rules! label_enum {
+    (
+        #[label = $label:literal]
+        $(#[$m:meta])*
+        $v:vis enum $name:ident {
+            $(
+                $(#[$variant_attr:meta])*
+                $variant:ident $(= $value:literal)?
+            ),+ $(,)?
+        }
+    ) => {
+        $(#[$m])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        $v enum $name {
+            $(
+                $(#[$variant_attr])*
+                $variant,
+            )+
+        }
+
+        impl $crate::LabelEnum for $name {
+            const LABEL: &'static str = $label;
+            const VALUES: &'static [&'static str] = &[
+                $($crate::label_enum!(@value $variant $($value)?)),+
+            ];
+
+            fn as_label(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $crate::label_enum!(@value $variant $($value)?),)+
+                }
+            }
+        }
+    };
+    (@value $variant:ident) => {
+        ::std::stringify!($variant)
+    };
+    (@value $variant:ident $value:literal) => {
+        $value
+    };
+}
+
+/// Builds all combinations of the given label values, e.g. to pre-initialize vector metrics.
+///
+/// Each dimension lists the values of one label. The combinations are returned in the order
+/// of the dimensions, varying the last label fastest.
+///
+/// ```
+/// use prometheus::{IntCounterVec, Opts};
+///
+/// let requests = IntCounterVec::new(Opts::new("requests", "Requests"), &["method", "status"])
+///     .unwrap();
+/// for labels in prometheus_macros::cartesian(&[&["GET", "POST"], &["2xx", "5xx"]]) {
+///     requests.with_label_values(&labels);
+/// }
+/// ```
+pub fn cartesian<'a>(dimensions: &[&[&'a str]]) -> Vec<Vec<&'a str>> {
+    dimensions
+        .iter()
+        .fold(vec![Vec::new()], |combinations, values| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push(*value);
+                        combination
+                    })
+                })
+                .collect()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    label_enum! {
+        #[label = "method"]
+        #[allow(dead_code)]
+        enum HttpMethod {
+            Get = "GET",
+            Post = "POST",
+            Delete,
+        }
+    }
+
+    #[test]
+    fn label_enum_values() {
+        assert_eq!(HttpMethod::LABEL, "method");
+        assert_eq!(HttpMethod::VALUES, ["GET", "POST", "Delete"]);
+        assert_eq!(HttpMethod::Post.as_label(), "POST");
+    }
+
+    #[test]
+    fn cartesian_two_dimensions() {
+        assert_eq!(
+            cartesian(&[&["GET", "POST"], &["2xx", "5xx"]]),
+            [
+                ["GET", "2xx"],
+                ["GET", "5xx"],
+                ["POST", "2xx"],
+                ["POST", "5xx"]
+            ]
+        );
+        assert_eq!(cartesian(&[&["GET"], &[]]), Vec::<Vec<&str>>::new());
+    }
+}
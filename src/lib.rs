@@ -33,14 +33,533 @@
 //! metric.custom_gauge().set(420);
 //! metric.custom_hist_vec().with_label_values(&["a", "b"]).observe(0.5);
 //! ```
+//!
+//! # `no_std`
+//!
+//! The crate requires `std`, as [`prometheus`] does. The [`Opts`] builder itself only needs
+//! `alloc` for its owned names and buckets, but it is of no use without the conversions into
+//! the [`prometheus`] metric types, which also build the const label map as a `HashMap`.
+//! Gating the builder behind a `std` feature would thus leave an `alloc`-only build without
+//! any metrics, so the crate doesn't offer one.
 
 #![deny(missing_docs)]
 
+mod builder;
+mod combine;
+mod const_name;
+mod dynamic;
+mod error;
+mod guard;
+mod health;
+mod histogram;
+#[cfg(feature = "json")]
+mod json;
+mod labels;
+#[cfg(feature = "tower")]
+mod middleware;
+mod pattern;
+pub mod prelude;
+mod quick;
+mod registry;
+#[cfg(feature = "hyper")]
+mod scrape;
+#[cfg(feature = "test-util")]
+mod test_util;
+mod timestamped;
+
+pub use builder::{RegistryBuilder, TupleAppend};
+pub use combine::Combined;
+pub use dynamic::{register_from_iter, DynMetric, MetricKind};
+pub use error::Error;
+pub use guard::InFlightGuard;
+pub use health::CounterSnapshot;
+pub use histogram::{histogram_deltas, slo_fractions};
+#[cfg(feature = "json")]
+pub use json::gather_json;
+pub use labels::{cartesian, LabelEnum};
+#[cfg(feature = "tower")]
+pub use middleware::{MetricsFuture, MetricsLayer, MetricsService, RequestMetrics};
+/// Derives the `register` method and the metric accessors for a struct of metrics.
+///
+/// This is an alternative to [`composite_metric!`] for a plain struct, with the metric
+/// options given in a `#[metric(...)]` attribute per field. Supported options are `name`,
+/// `desc`, `labels` and `buckets`, all of which behave like their [`composite_metric!`]
+/// counterparts.
+///
+/// ```
+/// use prometheus::{HistogramVec, IntGauge};
+/// use prometheus_macros::CompositeMetric;
+///
+/// #[derive(CompositeMetric)]
+/// struct ServerMetric {
+///     /// Current number of connections.
+///     #[metric(name = "custom_gauge", desc = "Example gauge metric")]
+///     custom_gauge: IntGauge,
+///     #[metric(
+///         name = "custom_hist_vec",
+///         desc = "Example histogram vec",
+///         labels = ["foo", "bar"],
+///         buckets = [0.01, 0.1, 0.2],
+///     )]
+///     custom_hist_vec: HistogramVec,
+/// }
+///
+/// let metric = ServerMetric::register(prometheus::default_registry())
+///     .expect("failed to register metrics to default registry");
+/// metric.custom_gauge().set(420);
+/// metric.custom_hist_vec().with_label_values(&["a", "b"]).observe(0.5);
+/// ```
+#[cfg(feature = "derive")]
+pub use prometheus_macros_derive::CompositeMetric;
+pub use registry::clear_registry;
+#[cfg(feature = "hyper")]
+pub use scrape::scrape_handler;
+#[cfg(feature = "test-util")]
+pub use test_util::{assert_registry_metric_count, reset_histogram};
+pub use timestamped::TimestampedGauge;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+
+use prometheus::core::{Atomic, Collector, GenericGauge};
 use prometheus::{
-    self, Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec,
-    IntCounterVec, IntGauge, IntGaugeVec, Opts as PrometheusOpts,
+    self, Counter, CounterVec, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGaugeVec, Opts as PrometheusOpts, Registry,
 };
 
+/// The `Content-Type` of the text exposition format, as served by scrape handlers.
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The `Content-Type` of the OpenMetrics text format.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[doc(hidden)]
+pub mod __private {
+    use prometheus::core::Number;
+    use prometheus::core::{
+        Atomic, Collector, GenericCounter, GenericCounterVec, GenericGauge, MetricVec,
+        MetricVecBuilder,
+    };
+    use prometheus::proto::{MetricFamily, MetricType};
+    use std::any::TypeId;
+    use std::cell::Cell;
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Mutex, OnceLock, PoisonError};
+    use std::time::{Duration, SystemTime};
+
+    pub use crate::const_name::ConstName;
+    pub use crate::health::counter_total;
+    pub use crate::histogram::cumulative_buckets;
+    #[cfg(feature = "json")]
+    pub use crate::json::{dashboard_spec, metric_spec};
+    pub use crate::pattern::LabelPattern;
+    #[cfg(feature = "test-util")]
+    pub use crate::test_util::metric_value;
+    pub use paste::paste;
+    #[cfg(feature = "json")]
+    pub use serde_json;
+
+    fn sorted_families(collector: &dyn Collector) -> Vec<MetricFamily> {
+        let mut families = collector.collect();
+        for family in &mut families {
+            family.mut_metric().sort_by_cached_key(|m| {
+                m.get_label()
+                    .iter()
+                    .map(|l| (l.name().to_owned(), l.value().to_owned()))
+                    .collect::<Vec<_>>()
+            });
+        }
+        families
+    }
+
+    /// Compares the current values (and label sets) of two collectors.
+    pub fn values_eq(a: &dyn Collector, b: &dyn Collector) -> bool {
+        sorted_families(a) == sorted_families(b)
+    }
+
+    /// Flattens the description into a single trimmed line and substitutes placeholders.
+    pub fn format_desc(desc: &str, context: &[(&str, &str)]) -> String {
+        let desc = desc
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        context.iter().fold(desc, |desc, (key, value)| {
+            desc.replace(&format!("{{{key}}}"), value)
+        })
+    }
+
+    /// Replaces the metric name with the transformed one, if any.
+    pub fn rename<'a>(
+        opts: crate::Opts<'a>,
+        rename: &dyn Fn(&str) -> Option<String>,
+    ) -> crate::Opts<'a> {
+        match rename(&opts.name) {
+            Some(name) => crate::Opts {
+                name: name.into(),
+                ..opts
+            },
+            None => opts,
+        }
+    }
+
+    /// Replaces the buckets of a histogram field with the ones supplied at runtime, if any.
+    pub fn runtime_buckets<'a>(
+        opts: crate::Opts<'a>,
+        kind: &str,
+        field: &str,
+        buckets: &dyn Fn(&str) -> Option<Vec<f64>>,
+    ) -> crate::Opts<'a> {
+        match (kind == "histogram").then(|| buckets(field)).flatten() {
+            Some(buckets) => opts.with_buckets_iter(buckets),
+            None => opts,
+        }
+    }
+
+    /// Constructs a metric and registers it to the default registry.
+    pub fn register_default<T>(opts: crate::Opts<'_>) -> prometheus::Result<T>
+    where
+        T: for<'a> TryFrom<crate::Opts<'a>, Error = prometheus::Error>
+            + Collector
+            + Clone
+            + 'static,
+    {
+        let metric = T::try_from(opts)?;
+        prometheus::register(Box::new(metric.clone()))?;
+        Ok(metric)
+    }
+
+    /// Vector metrics, to name the type of their children.
+    pub trait VecChild {
+        type Child;
+
+        fn child(&self, values: &[&str]) -> Self::Child;
+
+        fn try_child(&self, values: &[&str]) -> prometheus::Result<Self::Child>;
+    }
+
+    impl<B: MetricVecBuilder> VecChild for MetricVec<B> {
+        type Child = B::M;
+
+        #[track_caller]
+        fn child(&self, values: &[&str]) -> Self::Child {
+            match self.get_metric_with_label_values(values) {
+                Ok(child) => child,
+                Err(err) => panic!("invalid label values {values:?}: {err}"),
+            }
+        }
+
+        fn try_child(&self, values: &[&str]) -> prometheus::Result<Self::Child> {
+            self.get_metric_with_label_values(values)
+        }
+    }
+
+    /// Returns the counter or gauge value of the series with the given label values, or `None`
+    /// if it doesn't exist. Unlike `with_label_values`, a missing series isn't created.
+    #[track_caller]
+    pub fn series_value(collector: &impl Collector, values: &[&str]) -> Option<f64> {
+        let labels = &collector.desc()[0].variable_labels;
+        if labels.len() != values.len() {
+            let err = prometheus::Error::InconsistentCardinality {
+                expect: labels.len(),
+                got: values.len(),
+            };
+            panic!("invalid label values {values:?}: {err}");
+        }
+        let families = collector.collect();
+        let metric = families.first()?.get_metric().iter().find(|metric| {
+            labels.iter().zip(values).all(|(label, value)| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|pair| pair.name() == label && pair.value() == *value)
+            })
+        })?;
+        match families[0].get_field_type() {
+            MetricType::COUNTER => Some(metric.get_counter().value()),
+            MetricType::GAUGE => Some(metric.get_gauge().value()),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` with a probability of `1 / rate`, using a thread-local xorshift generator.
+    pub fn sample(rate: u32) -> bool {
+        thread_local! {
+            static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+        }
+        if rate <= 1 {
+            return true;
+        }
+        let random = STATE.with(|state| {
+            let mut x = state.get();
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            state.set(x);
+            x
+        });
+        random.is_multiple_of(u64::from(rate))
+    }
+
+    /// Counters incremented by `<field>_inc_sampled`.
+    pub trait IncSampled {
+        fn inc_sampled(&self, rate: u32);
+    }
+
+    impl<P: Atomic> IncSampled for GenericCounter<P> {
+        fn inc_sampled(&self, rate: u32) {
+            if sample(rate) {
+                self.inc_by(Number::from_i64(rate.max(1).into()));
+            }
+        }
+    }
+
+    /// Histograms observed by `<field>_record`, with label values for vectors only.
+    pub trait Observe {
+        fn observe_with(&self, values: &[&str], v: f64);
+    }
+
+    impl Observe for prometheus::Histogram {
+        #[track_caller]
+        fn observe_with(&self, values: &[&str], v: f64) {
+            assert!(
+                values.is_empty(),
+                "invalid label values {values:?}: histogram has no labels"
+            );
+            self.observe(v);
+        }
+    }
+
+    impl Observe for prometheus::HistogramVec {
+        #[track_caller]
+        fn observe_with(&self, values: &[&str], v: f64) {
+            self.child(values).observe(v);
+        }
+    }
+
+    /// Counters incremented by `<field>_record`, with label values for vectors only.
+    pub trait Increment {
+        fn inc_with(&self, values: &[&str]);
+    }
+
+    impl<P: Atomic> Increment for GenericCounter<P> {
+        #[track_caller]
+        fn inc_with(&self, values: &[&str]) {
+            assert!(
+                values.is_empty(),
+                "invalid label values {values:?}: counter has no labels"
+            );
+            self.inc();
+        }
+    }
+
+    impl<P: Atomic> Increment for GenericCounterVec<P> {
+        #[track_caller]
+        fn inc_with(&self, values: &[&str]) {
+            self.child(values).inc();
+        }
+    }
+
+    /// Returns the metric catalog of `T`, building it on the first call per type.
+    ///
+    /// Statics in generic impls are shared by all instantiations, so catalogs are kept by
+    /// type.
+    pub fn catalog_of<T: 'static>(
+        init: impl FnOnce() -> Vec<(&'static str, &'static str)>,
+    ) -> &'static [(&'static str, &'static str)] {
+        type Catalog = &'static [(&'static str, &'static str)];
+        static CATALOGS: Mutex<Vec<(TypeId, Catalog)>> = Mutex::new(Vec::new());
+
+        let mut catalogs = CATALOGS.lock().unwrap_or_else(PoisonError::into_inner);
+        match catalogs.iter().find(|(id, _)| *id == TypeId::of::<T>()) {
+            Some((_, catalog)) => catalog,
+            None => {
+                let catalog = Vec::leak(init());
+                catalogs.push((TypeId::of::<T>(), catalog));
+                catalog
+            }
+        }
+    }
+
+    /// Clones the registry of a `#[registry]` field, or creates one if the registrar has none.
+    pub fn registry_or_new(registrar: &impl crate::Registrar) -> prometheus::Registry {
+        registrar.registry().cloned().unwrap_or_default()
+    }
+
+    /// Strips the `r#` of a stringified raw identifier.
+    pub fn field_name(name: &'static str) -> &'static str {
+        name.strip_prefix("r#").unwrap_or(name)
+    }
+
+    /// Names the field and both help texts if the metric conflicts with a registered one.
+    ///
+    /// The registered help text is looked up in the gathered families, as the registry doesn't
+    /// expose its descriptors. Vector metrics without any series aren't gathered, so a conflict
+    /// with one of those keeps the original error.
+    pub fn register_error(
+        err: prometheus::Error,
+        registry: Option<&prometheus::Registry>,
+        field: &str,
+        collector: &dyn Collector,
+    ) -> prometheus::Error {
+        let Some(registry) = registry else {
+            return err;
+        };
+        // Without const labels, a conflicting help text is reported as already registered.
+        match &err {
+            prometheus::Error::AlreadyReg => {}
+            prometheus::Error::Msg(msg) if msg.contains("different help string") => {}
+            _ => return err,
+        }
+        for desc in collector.desc() {
+            let registered = registry
+                .gather()
+                .into_iter()
+                .find(|family| family.name() == desc.fq_name);
+            if let Some(family) = registered.filter(|family| family.help() != desc.help) {
+                return prometheus::Error::Msg(format!(
+                    "metric `{field}` ({}) has help {:?}, but is already registered with help {:?}",
+                    desc.fq_name,
+                    desc.help,
+                    family.help(),
+                ));
+            }
+        }
+        err
+    }
+
+    pub const fn is_strictly_increasing(buckets: &[f64]) -> bool {
+        let mut i = 1;
+        while i < buckets.len() {
+            if buckets[i - 1] >= buckets[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    fn unix_time() -> Duration {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    /// The histogram fields whose lowest bucket bound is zero or negative, so negative
+    /// observations are valid. Computed once when the struct is built.
+    #[derive(Clone, Debug, Default)]
+    pub struct NonPositiveBuckets(Vec<&'static str>);
+
+    impl NonPositiveBuckets {
+        /// Adds the field if the lowest bucket bound of the histogram isn't positive.
+        pub fn push(&mut self, field: &'static str, histogram: &prometheus::Histogram) {
+            let lowest = histogram
+                .collect()
+                .first()
+                .and_then(|family| cumulative_buckets(family).first().map(|&(bound, _)| bound));
+            if lowest.is_some_and(|bound| bound <= 0.0) {
+                self.0.push(field);
+            }
+        }
+
+        /// Returns whether the field was added.
+        pub fn contains(&self, field: &str) -> bool {
+            self.0.contains(&field)
+        }
+    }
+
+    /// The recording flag of `#[enabled_gate]`, enabled initially.
+    #[derive(Debug)]
+    pub struct EnabledFlag(AtomicBool);
+
+    impl EnabledFlag {
+        /// Returns whether recording is enabled.
+        pub fn get(&self) -> bool {
+            self.0.load(Ordering::Relaxed)
+        }
+
+        /// Enables or disables recording.
+        pub fn set(&self, enabled: bool) {
+            self.0.store(enabled, Ordering::Relaxed);
+        }
+    }
+
+    impl Default for EnabledFlag {
+        fn default() -> Self {
+            Self(AtomicBool::new(true))
+        }
+    }
+
+    impl Clone for EnabledFlag {
+        fn clone(&self) -> Self {
+            Self(AtomicBool::new(self.get()))
+        }
+    }
+
+    /// Guards the one-time registration of lazily registered metrics.
+    #[derive(Clone, Debug, Default)]
+    pub struct LazyRegistration(OnceLock<Result<(), String>>);
+
+    impl LazyRegistration {
+        pub fn get_or_register(
+            &self,
+            register: impl FnOnce() -> prometheus::Result<()>,
+        ) -> Result<(), &str> {
+            self.0
+                .get_or_init(|| register().map_err(|err| err.to_string()))
+                .as_ref()
+                .map(|_| ())
+                .map_err(String::as_str)
+        }
+
+        /// Marks the metrics as registered, e.g. after an explicit registration.
+        pub fn mark_registered(&self) {
+            let _ = self.0.set(Ok(()));
+        }
+    }
+
+    pub trait SetNow {
+        fn set_now(&self);
+    }
+
+    impl<P: Atomic> SetNow for GenericGauge<P>
+    where
+        P::T: UnixTime,
+    {
+        fn set_now(&self) {
+            self.set(P::T::from_unix_time(unix_time()));
+        }
+    }
+
+    /// Gauge values that can represent a UNIX time.
+    pub trait UnixTime {
+        fn from_unix_time(time: Duration) -> Self;
+    }
+
+    impl UnixTime for f64 {
+        fn from_unix_time(time: Duration) -> Self {
+            time.as_secs_f64()
+        }
+    }
+
+    impl UnixTime for i64 {
+        fn from_unix_time(time: Duration) -> Self {
+            time.as_secs() as i64
+        }
+    }
+
+    impl UnixTime for u64 {
+        fn from_unix_time(time: Duration) -> Self {
+            time.as_secs()
+        }
+    }
+}
+
 /// Composes multiple prometheus metrics into one struct.
 ///
 /// # Example:
@@ -69,297 +588,5293 @@ use prometheus::{
 ///     metric.custom_hist_vec().with_label_values(&["a", "b"]).observe(0.5);
 /// }
 /// ```
-#[macro_export]
-macro_rules! composite_metric {
-    (
-        $(#[$m:meta])*
-        $v:vis struct $name:ident {
-            $(
-                #[name = $prom_name:literal]
-                #[desc = $prom_desc:literal]
-                $(#[labels = $prom_labels:expr])?
-                $(#[buckets = $prom_buckets:expr])?
-                $metric_name:ident: $metric_ty:ty
-            ),+
-            $(,)?
-        }
-    ) => {
-        $(#[$m])*
-        $v struct $name {
-            $(
-                $metric_name: $metric_ty,
-            )+
+///
+/// Outer attributes on the struct, such as `#[derive(Clone)]` or `#[non_exhaustive]`, are
+/// applied to the generated struct, including `cfg_attr` like `#[cfg_attr(test, derive(Clone))]`.
+/// A `#[cfg(...)]` on the struct guards the generated impls and helper types as well.
+///
+/// The struct may have const generic parameters, e.g. `struct ShardMetrics<const N: usize>`,
+/// whose values can be part of the names with [`const_name!`], like
+/// `#[name = const_name!("shard_", N, "_requests")]`. Each instantiation has distinct metrics.
+///
+/// `#[dotted_name = "http.server.requests"]` takes the name as dotted path instead, which is
+/// exposed as `http_server_requests`. The name is validated at compile time:
+///
+/// ```compile_fail
+/// use prometheus::IntCounter;
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[dotted_name = "http..requests"]
+///         #[desc = "description"]
+///         requests: IntCounter,
+///     }
+/// }
+/// ```
+///
+/// Descriptions are expressions, e.g. `#[desc = include_str!("desc.md")]`. Multi-line
+/// descriptions are flattened into a single trimmed line, as the help text is a single line.
+///
+/// Descriptions may contain `{key}` placeholders, which `register_with_context` substitutes
+/// with the values of a `(key, value)` context, so the same struct can be reused with distinct
+/// help texts. `register` leaves the descriptions as they are.
+///
+/// `register_with_template` applies the runtime namespace, subsystem and const labels of an
+/// [`OptsTemplate`] to every metric, e.g. to register the same struct once per tenant.
+///
+/// `register_with_name_fn` transforms the name of every metric with a closure, e.g. replacing
+/// dots with underscores to enforce a naming convention.
+///
+/// `register_with` takes the buckets of histogram fields from a closure called with the field
+/// name, overriding the `#[buckets]` attribute, e.g. to tune buckets from a config file.
+///
+/// Histogram buckets are given in seconds with `#[buckets = [...]]`, or in milliseconds with
+/// `#[buckets_ms = [...]]` which are converted to seconds. `#[buckets = default]` explicitly
+/// selects [`prometheus::DEFAULT_BUCKETS`]. `#[buckets = exp(start, factor, count)]` and
+/// `#[buckets = linear(start, width, count)]` expand to [`prometheus::exponential_buckets`]
+/// and [`prometheus::linear_buckets`], with invalid arguments failing the registration.
+/// Literal bucket arrays are checked to be strictly increasing at compile time:
+///
+/// ```compile_fail
+/// use prometheus::Histogram;
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "example_hist"]
+///         #[desc = "description"]
+///         #[buckets = [0.5, 0.1]]
+///         hist_metric: Histogram,
+///     }
+/// }
+/// ```
+///
+/// Empty literal bucket arrays are rejected at compile time as well, while other bucket
+/// expressions that turn out empty fail the registration:
+///
+/// ```compile_fail
+/// use prometheus::Histogram;
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "example_hist"]
+///         #[desc = "description"]
+///         #[buckets = []]
+///         hist_metric: Histogram,
+///     }
+/// }
+/// ```
+///
+/// `#[slo_buckets = [...]]` declares literal buckets that are SLO latency targets, e.g.
+/// `[0.3, 1.0]` for "95% under 300ms, 99% under 1s". It behaves like `#[buckets]` and
+/// additionally generates `<field>_slo_fractions`, returning the fraction of observations
+/// within each boundary, see [`slo_fractions`].
+///
+/// Field attributes may be wrapped in `#[cfg_attr(predicate, ...)]`, e.g. to select a bucket
+/// set with `#[cfg_attr(feature = "fine", buckets = [...])]` at compile time. Other field
+/// attributes, such as doc comments, are applied to the generated accessor.
+///
+/// # Generated methods
+///
+/// Besides an accessor per field, the following helpers are generated. Helpers taking label
+/// values panic at the call site if the number of values doesn't match.
+///
+/// - `<field>_name` for every metric, returning the registered name from its descriptor.
+/// - `<field>_display` for every metric, encoding just that metric in the text format.
+/// - `<field>_set_now` for gauges, setting the gauge to the current UNIX time in seconds,
+///   e.g. for "last success" timestamps.
+/// - `<field>_guard` for gauges, returning an [`InFlightGuard`] that increments the gauge and
+///   decrements it again when dropped.
+/// - `<field>_set_checked` for `Gauge` fields, rejecting NaN and infinite values.
+/// - `<field>_get` for `IntCounterVec` and `IntGaugeVec` fields, returning the value of the
+///   series with the given label values.
+/// - `<field>_total` for `CounterVec` and `IntCounterVec` fields, summing all series.
+/// - `<field>_buckets` for `Histogram` fields, returning the cumulative bucket counts.
+/// - `<field>_observe_checked` for `Histogram` fields, rejecting NaN and infinite values, and
+///   negative values unless the buckets cover them.
+/// - `<field>_reset` for `Histogram` fields with the `test-util` feature, replacing the
+///   histogram with a fresh one, see `reset_histogram`.
+/// - `<field>_is_observed` for `Histogram` and `HistogramVec` fields, telling whether anything
+///   was observed yet, as dashboards break on histograms without observations.
+/// - `<field>_record` for histogram fields with `#[pair_with = counter_field]`, observing the
+///   histogram and incrementing the linked counter with the same label values in one call.
+/// - `<field>_for` for `HistogramVec` fields, returning the histogram of the given label
+///   values for repeated observations.
+/// - `<field>_inc_sampled` for counter fields, incrementing by the sampling rate for a
+///   random share of the calls to reduce contention on hot counters.
+/// - `<field>_inc_by_saturating` for `IntCounter` fields, capping the counter at `u64::MAX`
+///   instead of wrapping around.
+///
+/// Histograms are timed through their accessor, e.g. `metric.latency().start_timer()` returns
+/// a timer observing the elapsed seconds on drop. Summary fields are not supported, as
+/// [`prometheus`] 0.14 only exposes summaries in the protobuf types, without a metric type
+/// that observes into them, so there is nothing to time into either.
+///
+/// With `#[counter_health]` on the struct, `counter_snapshot` captures the values of all
+/// counter fields in a [`CounterSnapshot`]. Comparing it with a later snapshot tells which
+/// counters advanced and which stalled, e.g. for liveness probes.
+///
+/// With `#[enabled_gate]` on the struct, `<field>_if_enabled` returns the metric only while
+/// recording is enabled, so hot paths skip the atomic writes after `set_enabled(false)`. Each
+/// instance has its own flag and recording is enabled initially.
+///
+/// With `#[combine]` on the struct, `merge` joins the metrics with another metric set into a
+/// [`Combined`], e.g. to split one logical set across two structs sharing a registry.
+///
+/// With `#[collector]` on the struct, it implements [`Collector`](prometheus::core::Collector)
+/// for all its metrics, so it can be registered as a single collector, e.g. to another
+/// registry. This is an alternative to `register`, not an addition: registering the struct
+/// to the same registry its fields were registered to fails with
+/// [`AlreadyReg`](prometheus::Error::AlreadyReg), as the descriptors are already taken.
+/// `#[registry]` fields are not part of the collector.
+///
+/// With `#[strict]` on the struct, field attributes the macro doesn't know are rejected at
+/// compile time instead of being passed on to the accessor, which catches typos. Besides the
+/// attributes of the macro, docs and the `allow`, `deprecated` and `must_use` attributes are
+/// accepted.
+///
+/// ```compile_fail
+/// use prometheus::IntCounterVec;
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     #[strict]
+///     struct CompositeMetric {
+///         #[name = "requests"]
+///         #[desc = "description"]
+///         #[lables = ["method"]]
+///         requests: IntCounterVec,
+///     }
+/// }
+/// ```
+///
+/// With `#[async_register]` on the struct, `register_async` registers the metrics to an
+/// [`AsyncRegistry`]. `#[registry]` fields are not supported in this mode.
+///
+/// `metric` returns a field as [`Collector`](prometheus::core::Collector) by its id in the
+/// generated `<Struct>MetricId` enum, which has a variant per metric field, e.g.
+/// `CompositeMetricMetricId::RequestCount` for the `request_count` field of `CompositeMetric`.
+///
+/// `metric_catalog` lists the `(name, type)` pairs of all metrics, with the type derived from
+/// the field type, e.g. `"counter"`. This suits tests asserting the exposed metrics.
+/// Field types of other collectors are listed as `"untyped"`. There is no untyped field type
+/// itself, as the text encoder of [`prometheus`] 0.14 panics on untyped metric families, so
+/// bridged metrics are best exposed as gauges.
+///
+/// `collect_into` appends the metric families of all fields to a vector, so scrape loops can
+/// reuse a buffer. `gather_filtered` gathers a registry shared with other metrics, keeping
+/// only the families of the struct's own fields.
+///
+/// `from_parts` builds the struct from already constructed metrics, one argument per field
+/// in declaration order, skipping the registration. With `#[lazy_register]`, the metrics are
+/// still registered to the default registry on first access.
+///
+/// `FIELD_COUNT` and `field_count` give the number of metric fields, excluding `#[registry]`
+/// fields, e.g. as capacity hint when collecting the metrics.
+///
+/// With the `json` feature, `dashboard_spec` describes the name, type, help and labels of
+/// every metric as JSON, e.g. to generate Grafana dashboards. It only uses the attributes, so
+/// nothing is gathered.
+///
+/// `as_text` encodes the metrics in the text format through a temporary registry, so even
+/// unregistered metrics can be inspected.
+///
+/// `&Struct` implements [`IntoIterator`] over a boxed [`Collector`] per metric field, so it
+/// plugs into APIs accepting collectors. The boxed collectors share the values of the fields
+/// and are yielded in declaration order, so export sinks see a stable order.
+///
+/// `try_register` registers like `register`, but returns an [`Error`] naming the metric that
+/// failed to register.
+///
+/// `register_once` registers the metrics only on the first call and returns clones of that
+/// instance afterwards, so concurrent initialization doesn't fail with duplicate errors.
+///
+/// With `#[derive_value_eq]` on the struct, a `values_eq` method compares the current values
+/// of all metrics with another instance, e.g. for snapshot based tests. Histograms compare
+/// their sum, count and bucket counts.
+///
+/// A field annotated with `#[example = "..."]` gets an `# Examples` section with the given
+/// code in the accessor docs. The example is not run as a doctest, since it usually refers to
+/// an instance of the struct.
+///
+/// ```
+/// #![deny(missing_docs)]
+/// //! Metrics of a documented crate.
+/// use prometheus::IntGauge;
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     /// Documented metrics.
+///     pub struct CompositeMetric {
+///         /// The number of active connections.
+///         #[name = "active_connections"]
+///         #[desc = "Number of active connections"]
+///         #[example = "metric.active_connections().set(5);"]
+///         active_connections: IntGauge,
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// For literal `#[labels = [...]]`, a `<Struct><Field>Labels` struct is emitted next to the
+/// struct, with a `&str` field per label. `<field>_with_labels` takes it to bind the label
+/// values by name rather than by position. Both are skipped if a label is a Rust keyword,
+/// like `"type"`, as it can't name a field:
+///
+/// ```
+/// use prometheus::{IntCounterVec, Registry};
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "http_requests"]
+///         #[desc = "Number of HTTP requests"]
+///         #[labels = ["method", "status"]]
+///         http_requests: IntCounterVec,
+///     }
+/// }
+///
+/// let metric = CompositeMetric::register(&Registry::new()).unwrap();
+/// let labels = CompositeMetricHttpRequestsLabels { status: "200", method: "GET" };
+/// metric.http_requests_with_labels(labels).inc();
+/// assert_eq!(metric.http_requests_get(&["GET", "200"]), 1);
+/// ```
+///
+/// `#[label_patterns = [...]]` constrains the values of each label with a pattern, e.g.
+/// `"^(GET|POST)$"`. `<field>_checked` rejects label values that don't match their pattern,
+/// which guards against a cardinality blow-up from malformed values. The patterns support
+/// a small subset of regular expressions: literals, `.`, the `*`, `+` and `?` quantifiers,
+/// and alternations with `|` in parentheses. They always match the whole value.
+///
+/// ```
+/// use prometheus::{IntCounterVec, Registry};
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "http_requests"]
+///         #[desc = "Number of HTTP requests"]
+///         #[labels = ["method", "path"]]
+///         #[label_patterns = ["^(GET|POST|PUT)$", ".*"]]
+///         http_requests: IntCounterVec,
+///     }
+/// }
+///
+/// let metric = CompositeMetric::register(&Registry::new()).unwrap();
+/// metric.http_requests_checked(&["GET", "/"]).unwrap().inc();
+/// assert!(metric.http_requests_checked(&["get /", "/"]).is_err());
+/// ```
+///
+/// With `#[labels_from = Enum]` instead of `#[labels]`, the label and its values come from a
+/// [`LabelEnum`]. All values are pre-initialized and `<field>_with` returns the series of a
+/// variant.
+///
+/// Fields may be raw identifiers such as `r#type`, the generated helpers are named without
+/// the `r#`, e.g. `type_name`.
+///
+/// With `#[default_desc = "..."]` on the struct, fields without a `#[desc]` use the given
+/// description, while a `#[desc]` on the field takes precedence.
+///
+/// With `#[default_buckets = [...]]` on the struct, histogram fields without `#[buckets]` use
+/// the given buckets, in any of the `#[buckets]` forms. A `#[buckets]` on the field takes
+/// precedence.
+///
+/// With `#[getter_prefix = "get_"]` on the struct, the accessors are prefixed, e.g.
+/// `get_custom_gauge`. The other generated methods keep their names.
+///
+/// With `#[prefer_int]` on the struct, fields declared with the bare types `Gauge` and
+/// `Counter` become [`IntGauge`](prometheus::IntGauge) and [`IntCounter`] fields, including
+/// their accessors. Paths such as `prometheus::Gauge` are kept, so float metrics can still be
+/// declared explicitly:
+///
+/// ```
+/// use prometheus::{Gauge, IntGauge, Registry};
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     #[prefer_int]
+///     struct CompositeMetric {
+///         #[name = "queue_size"]
+///         #[desc = "Number of queued jobs"]
+///         queue_size: Gauge,
+///         #[name = "queue_load"]
+///         #[desc = "Load of the queue"]
+///         load: prometheus::Gauge,
+///     }
+/// }
+///
+/// let metric = CompositeMetric::register(&Registry::new()).unwrap();
+/// let queue_size: &IntGauge = metric.queue_size();
+/// queue_size.set(3);
+/// metric.load().set(0.5);
+/// ```
+///
+/// With `#[max_labels = n]` on the struct, declaring more than `n` labels on a metric is a
+/// compile error, guarding against accidental high cardinality:
+///
+/// ```compile_fail
+/// use prometheus::IntCounterVec;
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     #[max_labels = 2]
+///     struct CompositeMetric {
+///         #[name = "requests"]
+///         #[desc = "description"]
+///         #[labels = ["method", "path", "user"]]
+///         requests: IntCounterVec,
+///     }
+/// }
+/// ```
+///
+/// # Lazy registration
+///
+/// With `#[lazy_register]` on the struct, `new_lazy` constructs the metrics without
+/// registering them. They are registered to the default registry the first time any metric
+/// accessor is called, or explicitly with `ensure_registered`. This suits libraries that
+/// don't control the application startup. A failed registration is not retried; the metrics
+/// stay usable but are not exported, and `ensure_registered` returns the error message.
+///
+/// ```
+/// use std::sync::LazyLock;
+///
+/// use prometheus::Counter;
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     #[lazy_register]
+///     struct LibraryMetric {
+///         #[name = "library_calls"]
+///         #[desc = "Example counter metric"]
+///         calls: Counter,
+///     }
+/// }
+///
+/// static METRIC: LazyLock<LibraryMetric> =
+///     LazyLock::new(|| LibraryMetric::new_lazy().expect("valid metrics"));
+///
+/// METRIC.calls().inc();
+/// assert_eq!(prometheus::gather().len(), 1);
+/// ```
+///
+/// # Registry field
+///
+/// A field annotated with `#[registry]` stores the registry the metrics are registered to.
+/// `register` keeps a handle to the given registry, while `new` creates a fresh one.
+/// The stored registry is gathered with `gather`.
+///
+/// ```
+/// use prometheus::{IntGauge, Registry};
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[registry]
+///         registry: Registry,
+///         #[name = "custom_gauge"]
+///         #[desc = "Example gauge metric"]
+///         custom_gauge: IntGauge,
+///     }
+/// }
+///
+/// let metric = CompositeMetric::new().expect("failed to register metrics");
+/// metric.custom_gauge().set(420);
+/// assert_eq!(metric.gather().len(), 1);
+/// ```
+#[macro_export]
+macro_rules! composite_metric {
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[derive_value_eq] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (derive_value_eq)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[async_register] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (async_register)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[counter_health] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (counter_health)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[getter_prefix = $prefix:literal] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (getter_prefix $prefix)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[default_buckets = $($buckets:tt)+] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (default_buckets $($buckets)+)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[default_desc = $desc:expr] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (default_desc $desc)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[max_labels = $max:literal] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (max_labels $max)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[enabled_gate] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (enabled_gate)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[collector] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (collector)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[strict] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (strict)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[combine] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (combine)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[prefer_int] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (prefer_int)] [$($m)*] $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[lazy_register] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)* (lazy_register)] [$($m)*] $($rest)*);
+    };
+    // A `cfg` on the struct guards all generated items along with the struct.
+    (@attrs $opts:tt $metas:tt #[cfg($pred:meta)] $($rest:tt)*) => {
+        #[cfg($pred)]
+        $crate::composite_metric!(@attrs $opts $metas $($rest)*);
+    };
+    (@attrs [$($opt:tt)*] [$($m:tt)*] #[$attr:meta] $($rest:tt)*) => {
+        $crate::composite_metric!(@attrs [$($opt)*] [$($m)* #[$attr]] $($rest)*);
+    };
+    (@attrs $opts:tt $metas:tt $v:vis struct $name:ident { $($fields:tt)* }) => {
+        $crate::composite_metric!(@generics $opts $metas $v struct $name [] [] { $($fields)* });
+    };
+    (
+        @attrs $opts:tt $metas:tt
+        $v:vis struct $name:ident<$(const $param:ident: $param_ty:ty),+ $(,)?> { $($fields:tt)* }
+    ) => {
+        $crate::composite_metric!(
+            @generics $opts $metas $v struct $name
+            [$(const $param: $param_ty),+] [$($param),+] { $($fields)* }
+        );
+    };
+    (
+        @generics $opts:tt $metas:tt $v:vis struct $name:ident $params:tt $args:tt
+        { $($fields:tt)* }
+    ) => {
+        $crate::composite_metric!(
+            @fields $opts $metas $v struct $name $params $args [] { $($fields)* }
+        );
+    };
+
+    // Wraps each field type in brackets, so paths with a leading `::` can be forwarded, and
+    // maps bare `Gauge` and `Counter` field types to their integer variants with
+    // `#[prefer_int]`.
+    (@fields $opts:tt $metas:tt $v:vis struct $name:ident $params:tt $args:tt [$($done:tt)*] {}) => {
+        $crate::composite_metric!(@struct $opts $metas $v struct $name $params $args { $($done)* });
+    };
+    (
+        @fields $opts:tt $metas:tt $v:vis struct $name:ident $params:tt $args:tt [$($done:tt)*] {
+            $(#[$($field_attr:tt)*])* $field_name:ident: Gauge $(, $($rest:tt)*)?
+        }
+    ) => {
+        $crate::__composite_metric_opt!(@if prefer_int $opts {
+            $crate::composite_metric!(
+                @fields $opts $metas $v struct $name $params $args
+                [$($done)* $(#[$($field_attr)*])* $field_name: [::prometheus::IntGauge],]
+                { $($($rest)*)? }
+            );
+        } else {
+            $crate::composite_metric!(
+                @fields $opts $metas $v struct $name $params $args
+                [$($done)* $(#[$($field_attr)*])* $field_name: [Gauge],]
+                { $($($rest)*)? }
+            );
+        });
+    };
+    (
+        @fields $opts:tt $metas:tt $v:vis struct $name:ident $params:tt $args:tt [$($done:tt)*] {
+            $(#[$($field_attr:tt)*])* $field_name:ident: Counter $(, $($rest:tt)*)?
+        }
+    ) => {
+        $crate::__composite_metric_opt!(@if prefer_int $opts {
+            $crate::composite_metric!(
+                @fields $opts $metas $v struct $name $params $args
+                [$($done)* $(#[$($field_attr)*])* $field_name: [::prometheus::IntCounter],]
+                { $($($rest)*)? }
+            );
+        } else {
+            $crate::composite_metric!(
+                @fields $opts $metas $v struct $name $params $args
+                [$($done)* $(#[$($field_attr)*])* $field_name: [Counter],]
+                { $($($rest)*)? }
+            );
+        });
+    };
+    (
+        @fields $opts:tt $metas:tt $v:vis struct $name:ident $params:tt $args:tt [$($done:tt)*] {
+            $(#[$($field_attr:tt)*])*
+            $field_name:ident: :: $($field_ty:ident)::+ $(<$($field_gen:ty),+>)?
+            $(, $($rest:tt)*)?
+        }
+    ) => {
+        $crate::composite_metric!(
+            @fields $opts $metas $v struct $name $params $args
+            [
+                $($done)* $(#[$($field_attr)*])*
+                $field_name: [:: $($field_ty)::+ $(<$($field_gen),+>)?],
+            ]
+            { $($($rest)*)? }
+        );
+    };
+    (
+        @fields $opts:tt $metas:tt $v:vis struct $name:ident $params:tt $args:tt [$($done:tt)*] {
+            $(#[$($field_attr:tt)*])*
+            $field_name:ident: $($field_ty:ident)::+ $(<$($field_gen:ty),+>)?
+            $(, $($rest:tt)*)?
+        }
+    ) => {
+        $crate::composite_metric!(
+            @fields $opts $metas $v struct $name $params $args
+            [
+                $($done)* $(#[$($field_attr)*])*
+                $field_name: [$($field_ty)::+ $(<$($field_gen),+>)?],
+            ]
+            { $($($rest)*)? }
+        );
+    };
+
+    (
+        @fields $opts:tt $metas:tt $v:vis struct $name:ident $params:tt $args:tt $done:tt {
+            $(#[$($field_attr:tt)*])* $field_name:ident $($rest:tt)*
+        }
+    ) => {
+        ::std::compile_error!(::std::concat!(
+            "expected a metric type path for field `", ::std::stringify!($field_name), "`"
+        ));
+    };
+    (@fields $($rest:tt)*) => {
+        ::std::compile_error!("expected named metric fields, like `field: Type`");
+    };
+
+    // Collects the hidden fields required by the struct options and field types, then emits
+    // the struct.
+    (@hidden_fields $opts:tt $def:tt) => {
+        $crate::__composite_metric_opt!(@if lazy_register $opts {
+            $crate::composite_metric!(
+                @hidden_histogram $opts $def [
+                    __lazy_registration: $crate::__private::LazyRegistration =
+                        ::std::default::Default::default(),
+                ]
+            );
+        } else {
+            $crate::composite_metric!(@hidden_histogram $opts $def []);
+        });
+    };
+    (
+        @hidden_histogram $opts:tt [
+            $metas:tt $v:vis $name:ident $params:tt $args:tt
+            [$($field_name:ident: [$($field_ty:tt)+])+]
+        ]
+        [$($hidden:tt)*]
+    ) => {
+        $crate::composite_metric!(@if_histogram [$([$($field_ty)+])+] {
+            $crate::composite_metric!(
+                @hidden_gate $opts [
+                    $metas $v $name $params $args [$($field_name: [$($field_ty)+])+]
+                ]
+                [
+                    $($hidden)*
+                    __non_positive_buckets: $crate::__private::NonPositiveBuckets = {
+                        let mut buckets = $crate::__private::NonPositiveBuckets::default();
+                        $(
+                            $crate::__composite_metric_field!(
+                                @non_positive_buckets buckets, $field_name: [$($field_ty)+]
+                            );
+                        )+
+                        buckets
+                    },
+                ]
+            );
+        } else {
+            $crate::composite_metric!(
+                @hidden_gate $opts [
+                    $metas $v $name $params $args [$($field_name: [$($field_ty)+])+]
+                ]
+                [$($hidden)*]
+            );
+        });
+    };
+    (@hidden_gate $opts:tt $def:tt [$($hidden:tt)*]) => {
+        $crate::__composite_metric_opt!(@if enabled_gate $opts {
+            $crate::composite_metric!(
+                @struct_def $def [
+                    $($hidden)*
+                    __enabled: $crate::__private::EnabledFlag = ::std::default::Default::default(),
+                ]
+            );
+        } else {
+            $crate::composite_metric!(@struct_def $def [$($hidden)*]);
+        });
+    };
+    (@if_histogram [] $then:tt else { $($else:tt)* }) => {
+        $($else)*
+    };
+    (@if_histogram [[$($seg:ident)? :: $($rest:tt)+] $($tys:tt)*] $then:tt else $else:tt) => {
+        $crate::composite_metric!(@if_histogram [[$($rest)+] $($tys)*] $then else $else);
+    };
+    (@if_histogram [[Histogram] $($tys:tt)*] { $($then:tt)* } else $else:tt) => {
+        $($then)*
+    };
+    (@if_histogram [$ty:tt $($tys:tt)*] $then:tt else $else:tt) => {
+        $crate::composite_metric!(@if_histogram [$($tys)*] $then else $else);
+    };
+    (
+        @struct_def [
+            [$(#[$m:meta])*] $v:vis $name:ident [$($params:tt)*] [$($args:tt)*]
+            [$($field_name:ident: [$($field_ty:tt)+])+]
+        ]
+        [$($hidden:ident: $hidden_ty:ty = $hidden_init:expr,)*]
+    ) => {
+        $(#[$m])*
+        $v struct $name<$($params)*> {
+            $(
+                $field_name: $($field_ty)+,
+            )+
+            $(
+                $hidden: $hidden_ty,
+            )*
+        }
+
+        impl<$($params)*> $name<$($args)*> {
+            fn __from_fields($($field_name: $($field_ty)+),+) -> Self {
+                $(let $hidden = $hidden_init;)*
+                Self {
+                    $($field_name,)+
+                    $($hidden,)*
+                }
+            }
+        }
+    };
+
+    (
+        @struct $opts:tt [$(#[$m:meta])*]
+        $v:vis struct $name:ident [$($params:tt)*] [$($args:tt)*] {
+            $(
+                $(#[$($field_attr:tt)*])*
+                $field_name:ident: [$($field_ty:tt)+]
+            ),+
+            $(,)?
+        }
+    ) => {
+        $crate::__composite_metric_opt!(@if strict $opts {
+            $(
+                $crate::__composite_metric_field!(@strict $field_name $(#[$($field_attr)*])*);
+            )+
+        });
+
+        $crate::composite_metric!(
+            @hidden_fields $opts [
+                [$(#[$m])*] $v $name [$($params)*] [$($args)*]
+                [$($field_name: [$($field_ty)+])+]
+            ]
+        );
+
+        impl<$($params)*> $name<$($args)*> {
+            $crate::__composite_metric_opt!(@if lazy_register $opts {
+                fn __mark_registered(&self) {
+                    self.__lazy_registration.mark_registered();
+                }
+
+                /// Constructs the metrics without registering them.
+                ///
+                /// The metrics are registered to the default registry on first access.
+                #[allow(dead_code)]
+                $v fn new_lazy() -> ::prometheus::Result<Self> {
+                    $(
+                        $crate::__composite_metric_field!(
+                            @init $opts ::prometheus::default_registry(), &[], &$crate::OptsTemplate::new(),
+                            &|_| None, &|_| None,
+                            $field_name: [$($field_ty)+],
+                            $(#[$($field_attr)*])*
+                        );
+                    )+
+
+                    Ok(Self::__from_fields($($field_name),+))
+                }
+
+                /// Registers the metrics to the default registry, unless this already happened.
+                ///
+                /// Returns the error message if the registration failed. A failed registration
+                /// is not retried, the metrics remain usable but are not exported.
+                #[allow(dead_code)]
+                $v fn ensure_registered(&self) -> ::std::result::Result<(), &str> {
+                    self.__lazy_registration
+                        .get_or_register(|| self.reregister(::prometheus::default_registry()))
+                }
+            } else {
+                fn __mark_registered(&self) {}
+            });
+        }
+
+        impl<$($params)*> $name<$($args)*> {
+            /// The number of metric fields, excluding `#[registry]` fields.
+            #[allow(dead_code)]
+            $v const FIELD_COUNT: usize = 0 $(
+                + $crate::__composite_metric_field!(@count $(#[$($field_attr)*])*)
+            )+;
+
+            /// Returns the number of metric fields, see `FIELD_COUNT`.
+            #[allow(dead_code)]
+            $v fn field_count() -> usize {
+                Self::FIELD_COUNT
+            }
+
+            /// Builds the struct from already constructed metrics, in the declaration order of
+            /// the fields, without registering them.
+            ///
+            /// This allows injecting metrics in tests or sharing them with other structs.
+            #[allow(dead_code, clippy::too_many_arguments)]
+            $v fn from_parts($($field_name: $($field_ty)+),+) -> Self {
+                Self::__from_fields($($field_name),+)
+            }
+
+            /// Constructs all metrics and registers them to the given registry.
+            #[allow(dead_code)]
+            $v fn register(
+                registry: &impl $crate::Registrar,
+            ) -> ::prometheus::Result<Self> {
+                Self::register_with_context(registry, &[])
+            }
+
+            /// Registers all metrics, substituting `{key}` placeholders in the descriptions
+            /// with the values of the given `(key, value)` context.
+            #[allow(dead_code)]
+            $v fn register_with_context(
+                registry: &impl $crate::Registrar,
+                context: &[(&str, &str)],
+            ) -> ::prometheus::Result<Self> {
+                Self::__register(registry, context, &$crate::OptsTemplate::new(), &|_| None, &|_| None)
+            }
+
+            /// Registers all metrics, applying the namespace, subsystem and const labels of the
+            /// given template to every metric.
+            #[allow(dead_code)]
+            $v fn register_with_template(
+                registry: &impl $crate::Registrar,
+                template: &$crate::OptsTemplate<'_>,
+            ) -> ::prometheus::Result<Self> {
+                Self::__register(registry, &[], template, &|_| None, &|_| None)
+            }
+
+            /// Registers all metrics, taking the buckets of histogram fields from the given
+            /// closure.
+            ///
+            /// The closure is called with the name of each histogram field, returned buckets
+            /// override the ones of the `#[buckets]` attribute, `None` keeps them.
+            #[allow(dead_code)]
+            $v fn register_with(
+                registry: &impl $crate::Registrar,
+                buckets: impl Fn(&str) -> ::std::option::Option<::std::vec::Vec<f64>>,
+            ) -> ::prometheus::Result<Self> {
+                Self::__register(registry, &[], &$crate::OptsTemplate::new(), &buckets, &|_| None)
+            }
+
+            /// Registers all metrics, transforming each metric name with the given closure,
+            /// e.g. to enforce a naming convention.
+            ///
+            /// The closure receives the name of the `#[name]` attribute, before the namespace
+            /// and subsystem of a template are prepended.
+            #[allow(dead_code)]
+            $v fn register_with_name_fn(
+                registry: &impl $crate::Registrar,
+                name_fn: impl Fn(&str) -> ::std::string::String,
+            ) -> ::prometheus::Result<Self> {
+                Self::__register(
+                    registry,
+                    &[],
+                    &$crate::OptsTemplate::new(),
+                    &|_| None,
+                    &|name| ::std::option::Option::Some(name_fn(name)),
+                )
+            }
+
+            fn __register(
+                registry: &impl $crate::Registrar,
+                context: &[(&str, &str)],
+                template: &$crate::OptsTemplate<'_>,
+                buckets: &dyn Fn(&str) -> ::std::option::Option<::std::vec::Vec<f64>>,
+                rename: &dyn Fn(&str) -> ::std::option::Option<::std::string::String>,
+            ) -> ::prometheus::Result<Self> {
+                $(
+                    $crate::__composite_metric_field!(
+                        @init $opts registry, context, template, buckets, rename,
+                        $field_name: [$($field_ty)+],
+                        $(#[$($field_attr)*])*
+                    );
+                )+
+
+                let metric = Self::__from_fields($($field_name),+);
+                metric.reregister(registry)?;
+                metric.__mark_registered();
+                Ok(metric)
+            }
+
+            /// Constructs and registers all metrics like `register`, naming the metric that
+            /// failed to register in the error.
+            #[allow(dead_code)]
+            $v fn try_register(
+                registry: &impl $crate::Registrar,
+            ) -> ::std::result::Result<Self, $crate::Error> {
+                $(
+                    $crate::__composite_metric_field!(
+                        @init $opts registry, &[], &$crate::OptsTemplate::new(), &|_| None, &|_| None,
+                        $field_name: [$($field_ty)+],
+                        $(#[$($field_attr)*])*
+                    );
+                )+
+
+                let metric = Self::__from_fields($($field_name),+);
+                $(
+                    $crate::__composite_metric_field!(
+                        @try_register metric, registry, $field_name, $(#[$($field_attr)*])*
+                    );
+                )+
+                metric.__mark_registered();
+                Ok(metric)
+            }
+
+            /// Registers the metrics on the first call only, subsequent calls return a clone of
+            /// the first successfully registered instance.
+            ///
+            /// The instance is shared per type, so later calls ignore the given registry. This
+            /// allows concurrent initialization without duplicate registration errors. If the
+            /// registration fails, the error is returned and the next call tries again.
+            #[allow(dead_code)]
+            $v fn register_once(
+                registry: &impl $crate::Registrar,
+            ) -> ::prometheus::Result<Self> {
+                // Statics can't name the type in generic impls, so instances are kept by type.
+                static INSTANCES: ::std::sync::Mutex<
+                    ::std::vec::Vec<::std::boxed::Box<dyn ::std::any::Any + ::std::marker::Send>>,
+                > = ::std::sync::Mutex::new(::std::vec::Vec::new());
+
+                let mut instances = INSTANCES
+                    .lock()
+                    .unwrap_or_else(::std::sync::PoisonError::into_inner);
+                let index = match instances.iter().position(|instance| instance.is::<Self>()) {
+                    ::std::option::Option::Some(index) => index,
+                    ::std::option::Option::None => {
+                        instances.push(::std::boxed::Box::new(Self::register(registry)?));
+                        instances.len() - 1
+                    }
+                };
+                let instance = instances[index]
+                    .downcast_ref::<Self>()
+                    .expect("instance of the type");
+                let metric = Self::__from_fields(
+                    $(::std::clone::Clone::clone(&instance.$field_name)),+
+                );
+                metric.__mark_registered();
+                Ok(metric)
+            }
+
+            /// Registers the already constructed metrics to another registry.
+            ///
+            /// The metrics are shared with the original registry, so accumulated values
+            /// are preserved.
+            #[allow(dead_code)]
+            $v fn reregister(
+                &self,
+                registry: &impl $crate::Registrar,
+            ) -> ::prometheus::Result<()> {
+                $(
+                    $crate::__composite_metric_field!(
+                        @reregister self, registry, $field_name, $(#[$($field_attr)*])*
+                    );
+                )+
+                Ok(())
+            }
+
+            /// Returns the names and types of all metrics, e.g. to assert the exposed metrics.
+            #[allow(dead_code, clippy::vec_init_then_push)]
+            $v fn metric_catalog() -> &'static [(&'static str, &'static str)] {
+                $crate::__private::catalog_of::<Self>(|| {
+                    let mut catalog = ::std::vec::Vec::with_capacity(Self::FIELD_COUNT);
+                    $(
+                        $crate::__composite_metric_field!(
+                            @catalog catalog, $field_name: [$($field_ty)+],
+                            $(#[$($field_attr)*])*
+                        );
+                    )+
+                    catalog
+                })
+            }
+
+            /// Appends the collected metric families to `out`, e.g. to reuse a scrape buffer.
+            #[allow(dead_code)]
+            $v fn collect_into(
+                &self,
+                out: &mut ::std::vec::Vec<::prometheus::proto::MetricFamily>,
+            ) {
+                $(
+                    $crate::__composite_metric_field!(
+                        @collect_into self, out, $field_name, $(#[$($field_attr)*])*
+                    );
+                )+
+            }
+
+            /// Encodes all metrics in the text exposition format, without registering them.
+            ///
+            /// The metrics are gathered from a temporary registry, e.g. to debug a struct that
+            /// isn't registered anywhere. Fails if the metrics can't be registered together,
+            /// e.g. on duplicate names, or can't be encoded.
+            #[allow(dead_code)]
+            $v fn as_text(&self) -> ::prometheus::Result<::std::string::String> {
+                let registry = ::prometheus::Registry::new();
+                for collector in self {
+                    registry.register(collector)?;
+                }
+                ::prometheus::TextEncoder::new().encode_to_string(&registry.gather())
+            }
+
+            /// Gathers the given registry, keeping only the metric families of this struct.
+            ///
+            /// This suits registries shared by several composite metrics.
+            #[allow(dead_code)]
+            $v fn gather_filtered(
+                &self,
+                registry: &impl $crate::RegistryLike,
+            ) -> ::std::vec::Vec<::prometheus::proto::MetricFamily> {
+                let mut names = ::std::vec::Vec::<&str>::with_capacity(Self::FIELD_COUNT);
+                $(
+                    $crate::__composite_metric_field!(
+                        @fq_names self, names, $field_name, $(#[$($field_attr)*])*
+                    );
+                )+
+                let mut families = $crate::RegistryLike::as_registry(registry).gather();
+                families.retain(|family| names.contains(&family.name()));
+                families
+            }
+
+            $crate::__composite_metric_json!(
+                @dashboard_spec $opts $v,
+                $([$field_name: [$($field_ty)+], $(#[$($field_attr)*])*])+
+            );
+
+            $crate::__composite_metric_opt!(@if derive_value_eq $opts {
+                /// Compares the current values of all metrics with another instance.
+                #[allow(dead_code)]
+                $v fn values_eq(&self, other: &Self) -> bool {
+                    true $(
+                        && $crate::__composite_metric_field!(
+                            @values_eq self, other, $field_name, $(#[$($field_attr)*])*
+                        )
+                    )+
+                }
+            });
+
+            $crate::__composite_metric_opt!(@if async_register $opts {
+                /// Constructs all metrics and registers them to the given async registry.
+                #[allow(dead_code)]
+                $v async fn register_async(
+                    registry: &impl $crate::AsyncRegistry,
+                ) -> ::prometheus::Result<Self> {
+                    $(
+                        $crate::__composite_metric_field!(
+                            @async_init $opts $field_name: [$($field_ty)+],
+                            $(#[$($field_attr)*])*
+                        );
+                    )+
+
+                    let metric = Self::__from_fields($($field_name),+);
+                    $(
+                        $crate::__composite_metric_field!(
+                            @async_register metric, registry, $field_name, $(#[$($field_attr)*])*
+                        );
+                    )+
+                    metric.__mark_registered();
+                    Ok(metric)
+                }
+            });
+
+            $crate::__composite_metric_opt!(@if counter_health $opts {
+                /// Takes a snapshot of all counter values to detect stalled counters.
+                #[allow(dead_code, clippy::vec_init_then_push)]
+                $v fn counter_snapshot(&self) -> $crate::CounterSnapshot {
+                    let mut counters = ::std::vec::Vec::with_capacity(Self::FIELD_COUNT);
+                    $(
+                        $crate::__composite_metric_field!(
+                            @counter_snapshot self, counters, $field_name: [$($field_ty)+],
+                            $(#[$($field_attr)*])*
+                        );
+                    )+
+                    ::std::iter::FromIterator::from_iter(counters)
+                }
+            });
+
+            $crate::__composite_metric_opt!(@if combine $opts {
+                /// Combines these metrics with another metric set, e.g. one registered later.
+                #[allow(dead_code)]
+                $v fn merge<O>(self, other: O) -> $crate::Combined<Self, O> {
+                    $crate::Combined::new(self, other)
+                }
+            });
+
+            $crate::__composite_metric_opt!(@if enabled_gate $opts {
+                /// Enables or disables recording through the `<field>_if_enabled` accessors.
+                ///
+                /// The flag belongs to this instance, other instances keep recording.
+                #[allow(dead_code)]
+                $v fn set_enabled(&self, enabled: bool) {
+                    self.__enabled.set(enabled);
+                }
+
+                /// Returns whether recording is enabled, see `set_enabled`.
+                #[allow(dead_code)]
+                $v fn is_enabled(&self) -> bool {
+                    self.__enabled.get()
+                }
+            });
+
+            $(
+                $crate::__composite_metric_field!(
+                    @methods $opts $v, $field_name: [$($field_ty)+],
+                    $(#[$($field_attr)*])*
+                );
+            )+
+        }
+
+        impl<$($params)*> ::std::iter::IntoIterator for &$name<$($args)*> {
+            type Item = ::std::boxed::Box<dyn ::prometheus::core::Collector>;
+            type IntoIter = ::std::vec::IntoIter<Self::Item>;
+
+            /// Returns a clone of every metric as boxed collector, sharing the values.
+            ///
+            /// The collectors are yielded in the declaration order of the fields.
+            #[allow(clippy::vec_init_then_push)]
+            fn into_iter(self) -> Self::IntoIter {
+                let mut collectors: ::std::vec::Vec<Self::Item> =
+                    ::std::vec::Vec::with_capacity($name::<$($args)*>::FIELD_COUNT);
+                $(
+                    $crate::__composite_metric_field!(
+                        @collectors self, collectors, $field_name, $(#[$($field_attr)*])*
+                    );
+                )+
+                collectors.into_iter()
+            }
+        }
+
+        $crate::__composite_metric_field!(
+            @metric_id $v $name [$($params)*] [$($args)*] []
+            [$([$field_name $(#[$($field_attr)*])*])+]
+        );
+
+        $crate::__composite_metric_opt!(@if collector $opts {
+            impl<$($params)*> ::prometheus::core::Collector for $name<$($args)*> {
+                /// Returns the descriptors of all metrics, in the declaration order of the fields.
+                #[allow(clippy::vec_init_then_push)]
+                fn desc(&self) -> ::std::vec::Vec<&::prometheus::core::Desc> {
+                    let mut descs = ::std::vec::Vec::with_capacity(Self::FIELD_COUNT);
+                    $(
+                        $crate::__composite_metric_field!(
+                            @descs self, descs, $field_name, $(#[$($field_attr)*])*
+                        );
+                    )+
+                    descs
+                }
+
+                fn collect(&self) -> ::std::vec::Vec<::prometheus::proto::MetricFamily> {
+                    let mut families = ::std::vec::Vec::with_capacity(Self::FIELD_COUNT);
+                    self.collect_into(&mut families);
+                    families
+                }
+            }
+        });
+
+        $(
+            $crate::__composite_metric_field!(
+                @max_labels $opts $field_name $(#[$($field_attr)*])*
+            );
+        )+
+        $crate::__composite_metric_field!(
+            @labels_structs $v $name [$($params)*] [$($args)*]
+            [$([$field_name: [$($field_ty)+] $(#[$($field_attr)*])*])+]
+        );
+    };
+    (@attrs $($rest:tt)*) => {
+        ::std::compile_error!(
+            "expected a struct with named metric fields, like `struct Name { field: Type, ... }`"
+        );
+    };
+    ($($input:tt)*) => {
+        $crate::composite_metric!(@attrs [] [] $($input)*);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __composite_metric_opt {
+    (@if $opt:ident [] { $($then:tt)* } $(else { $($else:tt)* })?) => {
+        $($($else)*)?
+    };
+    (
+        @if derive_value_eq [(derive_value_eq) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if async_register [(async_register) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if counter_health [(counter_health) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if enabled_gate [(enabled_gate) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if collector [(collector) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if strict [(strict) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if combine [(combine) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if prefer_int [(prefer_int) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if lazy_register [(lazy_register) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (
+        @if default_desc [(default_desc $desc:expr) $($rest:tt)*]
+        { $($then:tt)* } $(else { $($else:tt)* })?
+    ) => {
+        $($then)*
+    };
+    (@if $opt:ident [$skip:tt $($rest:tt)*] { $($then:tt)* } $(else { $($else:tt)* })?) => {
+        $crate::__composite_metric_opt!(
+            @if $opt [$($rest)*] { $($then)* } $(else { $($else)* })?
+        );
+    };
+
+    // Applies the `#[default_buckets]`, which a `#[buckets]` of the field overrides.
+    (@default_buckets [] $opts:ident) => {};
+    (@default_buckets [(default_buckets $($buckets:tt)+) $($rest:tt)*] $opts:ident) => {
+        $crate::__composite_metric_field!(@opts $opts, buckets = $($buckets)+);
+    };
+    (@default_buckets [$skip:tt $($rest:tt)*] $opts:ident) => {
+        $crate::__composite_metric_opt!(@default_buckets [$($rest)*] $opts);
+    };
+
+    // Binds the `#[default_desc]`, which a `#[desc]` of the field shadows.
+    (@default_desc [] $desc:ident, $context:expr) => {};
+    (@default_desc [(default_desc $default:expr) $($opts:tt)*] $desc:ident, $context:expr) => {
+        #[allow(unused_variables)]
+        let $desc = $crate::__private::format_desc($default, $context);
+    };
+    (@default_desc [$skip:tt $($opts:tt)*] $desc:ident, $context:expr) => {
+        $crate::__composite_metric_opt!(@default_desc [$($opts)*] $desc, $context);
+    };
+
+    // Emits an accessor, named with the `#[getter_prefix]` if given.
+    (@getter [] [$($kept:tt)*] $v:vis fn $name:ident $($rest:tt)*) => {
+        $($kept)*
+        $v fn $name $($rest)*
+    };
+    (
+        @getter [(getter_prefix $prefix:literal) $($opts:tt)*] [$($kept:tt)*]
+        $v:vis fn $name:ident $($rest:tt)*
+    ) => {
+        $crate::__private::paste! {
+            $($kept)*
+            $v fn [<$prefix $name>] $($rest)*
+        }
+    };
+    (@getter [$skip:tt $($opts:tt)*] [$($kept:tt)*] $($accessor:tt)*) => {
+        $crate::__composite_metric_opt! {
+            @getter [$($opts)*] [$($kept)*] $($accessor)*
+        }
+    };
+
+    // Asserts at compile time that the labels don't exceed the `#[max_labels]`.
+    (@max_labels [] $field_name:ident $labels:expr) => {};
+    (@max_labels [(max_labels $max:literal) $($opts:tt)*] $field_name:ident $labels:expr) => {
+        const _: () = ::std::assert!(
+            $labels.len() <= $max,
+            ::std::concat!(
+                "metric `", ::std::stringify!($field_name), "` declares more than ",
+                ::std::stringify!($max), " labels"
+            ),
+        );
+    };
+    (@max_labels [$skip:tt $($opts:tt)*] $field_name:ident $labels:expr) => {
+        $crate::__composite_metric_opt!(@max_labels [$($opts)*] $field_name $labels);
+    };
+}
+
+// Without the `test-util` feature, the test helpers are not generated.
+#[cfg(not(feature = "test-util"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __composite_metric_test_util {
+    ($($tt:tt)*) => {};
+}
+
+// Without the `json` feature, `dashboard_spec` is not generated.
+#[cfg(not(feature = "json"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __composite_metric_json {
+    ($($tt:tt)*) => {};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __composite_metric_field {
+    // Dispatches to the registry branch for fields annotated with `#[registry]`.
+    (@registry_or_metric [] { $($registry:tt)* } { $($metric:tt)* }) => {
+        $crate::__composite_metric_field! { $($metric)* }
+    };
+    (@registry_or_metric [#[registry] $($rest:tt)*] { $($registry:tt)* } { $($metric:tt)* }) => {
+        $crate::__composite_metric_field! { $($registry)* }
+    };
+    (@registry_or_metric [#[$($attr:tt)*] $($rest:tt)*] $registry:tt $metric:tt) => {
+        $crate::__composite_metric_field! { @registry_or_metric [$($rest)*] $registry $metric }
+    };
+
+    (
+        @init $opts:tt $registry:expr, $context:expr, $template:expr, $buckets:expr, $rename:expr,
+        $field_name:ident: [$($field_ty:tt)+], $(#[$($field_attr:tt)*])*
+    ) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_init $registry, $field_name: [$($field_ty)+] }
+            { @metric_init $opts $context, $template, $buckets, $rename, $field_name: [$($field_ty)+], $(#[$($field_attr)*])* }
+        }
+    };
+    (@registry_init $registry:expr, $field_name:ident: [$($field_ty:tt)+]) => {
+        let $field_name: $($field_ty)+ = $crate::__private::registry_or_new($registry);
+    };
+    (
+        @metric_init $opts:tt $context:expr, $template:expr, $buckets:expr, $rename:expr,
+        $metric_name:ident: [$($metric_ty:tt)+], $(#[$($metric_attr:tt)*])*
+    ) => {
+        $crate::__composite_metric_opt!(@if default_desc $opts {
+            $crate::__composite_metric_field!(@check $metric_name [] [desc] $(#[$($metric_attr)*])*);
+        } else {
+            $crate::__composite_metric_field!(@check $metric_name [] [] $(#[$($metric_attr)*])*);
+        });
+        let $metric_name: $($metric_ty)+ = {
+            $crate::__composite_metric_opt!(@default_desc $opts prom_desc, $context);
+            $(
+                $crate::__composite_metric_field!(
+                    @name_desc prom_name, prom_desc, $context, $($metric_attr)*
+                );
+            )*
+            let opts = $crate::__private::rename($crate::Opts::new(prom_name, &prom_desc), $rename)
+                .with_template($template);
+            $crate::__composite_metric_opt!(@default_buckets $opts opts);
+            $(
+                $crate::__composite_metric_field!(@opts opts, $($metric_attr)*);
+            )*
+            let opts = $crate::__private::runtime_buckets(
+                opts,
+                $crate::__composite_metric_field!(@kind_str [$($metric_ty)+]),
+                ::std::stringify!($metric_name),
+                $buckets,
+            );
+            let $metric_name: $($metric_ty)+ = opts.try_into()?;
+            $(
+                $crate::__composite_metric_field!(@post_init $metric_name, $($metric_attr)*);
+            )*
+            $metric_name
+        };
+    };
+
+    // Ensures the required `#[name]` and `#[desc]` attributes are present.
+    (@check $metric_name:ident [$name:tt] [$desc:tt]) => {};
+    (@check $metric_name:ident [] [$($desc:tt)?]) => {
+        ::std::compile_error!(::std::concat!(
+            "missing `#[name = ...]` attribute on metric `", ::std::stringify!($metric_name), "`"
+        ));
+    };
+    (@check $metric_name:ident [$name:tt] []) => {
+        ::std::compile_error!(::std::concat!(
+            "missing `#[desc = ...]` attribute on metric `", ::std::stringify!($metric_name), "`"
+        ));
+    };
+    (@check $metric_name:ident [$($name:tt)?] [$($desc:tt)?] #[name $($attr:tt)*] $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@check $metric_name [name] [$($desc)?] $($rest)*);
+    };
+    (
+        @check $metric_name:ident [$($name:tt)?] [$($desc:tt)?]
+        #[dotted_name $($attr:tt)*] $($rest:tt)*
+    ) => {
+        $crate::__composite_metric_field!(@check $metric_name [name] [$($desc)?] $($rest)*);
+    };
+    (@check $metric_name:ident [$($name:tt)?] [$($desc:tt)?] #[desc $($attr:tt)*] $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@check $metric_name [$($name)?] [desc] $($rest)*);
+    };
+    (
+        @check $metric_name:ident [$($name:tt)?] [$($desc:tt)?]
+        #[cfg_attr($pred:meta, $($attr:tt)*)] $($rest:tt)*
+    ) => {
+        $crate::__composite_metric_field!(
+            @check $metric_name [$($name)?] [$($desc)?] #[$($attr)*] $($rest)*
+        );
+    };
+    (@check $metric_name:ident [$($name:tt)?] [$($desc:tt)?] #[$($attr:tt)*] $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@check $metric_name [$($name)?] [$($desc)?] $($rest)*);
+    };
+
+    // Binds the metric name and description of a field attribute.
+    (@name_desc $name:ident, $desc:ident, $context:expr, name = $prom_name:expr) => {
+        let $name = $prom_name;
+    };
+    (@name_desc $name:ident, $desc:ident, $context:expr, dotted_name = $prom_name:expr) => {
+        let $name = $crate::__composite_metric_field!(@dotted_name $prom_name);
+    };
+    (@name_desc $name:ident, $desc:ident, $context:expr, desc = $prom_desc:expr) => {
+        let $desc = $crate::__private::format_desc($prom_desc, $context);
+    };
+    (@name_desc $name:ident, $desc:ident, $context:expr, cfg_attr($pred:meta, $($attr:tt)*)) => {
+        #[cfg($pred)]
+        $crate::__composite_metric_field!(@name_desc $name, $desc, $context, $($attr)*);
+    };
+    (@name_desc $name:ident, $desc:ident, $context:expr, $($attr:tt)*) => {};
+
+    // Normalizes a dotted metric name at compile time.
+    // The const item is evaluated even if the surrounding function is unused.
+    (@dotted_name $prom_name:expr) => {{
+        const NAME: &$crate::__private::ConstName =
+            &$crate::__private::ConstName::new().push_dotted($prom_name);
+        NAME.as_str()
+    }};
+
+    // Applies a field attribute to the metric options.
+    (@opts $opts:ident, labels = $prom_labels:expr) => {
+        let $opts = $opts.with_variable_labels(&$prom_labels[..]);
+    };
+    (@opts $opts:ident, labels_from = $label_enum:ty) => {
+        let $opts = $opts.with_variable_labels(&[<$label_enum as $crate::LabelEnum>::LABEL]);
+    };
+    (@opts $opts:ident, buckets = default) => {
+        let $opts = $opts.with_buckets(::prometheus::DEFAULT_BUCKETS);
+    };
+    (@opts $opts:ident, buckets = []) => {
+        ::std::compile_error!("histogram buckets must not be empty");
+    };
+    (@opts $opts:ident, buckets = [$($prom_bucket:literal),+ $(,)?]) => {
+        const _: () = ::std::assert!(
+            $crate::__private::is_strictly_increasing(&[$($prom_bucket),+]),
+            "histogram buckets must be strictly increasing",
+        );
+        let $opts = $opts.with_buckets(&[$($prom_bucket),+]);
+    };
+    (@opts $opts:ident, slo_buckets = [$($prom_bucket:literal),+ $(,)?]) => {
+        $crate::__composite_metric_field!(@opts $opts, buckets = [$($prom_bucket),+]);
+    };
+    (@opts $opts:ident, buckets = exp($start:expr, $factor:expr, $count:expr $(,)?)) => {
+        let buckets = ::prometheus::exponential_buckets($start, $factor, $count)?;
+        let $opts = $opts.with_buckets(&buckets);
+    };
+    (@opts $opts:ident, buckets = linear($start:expr, $width:expr, $count:expr $(,)?)) => {
+        let buckets = ::prometheus::linear_buckets($start, $width, $count)?;
+        let $opts = $opts.with_buckets(&buckets);
+    };
+    (@opts $opts:ident, buckets = $prom_buckets:expr) => {
+        let $opts = $opts.with_buckets(&$prom_buckets);
+    };
+    (@opts $opts:ident, buckets_ms = [$($prom_buckets_ms:expr),* $(,)?]) => {
+        let buckets = [$(f64::from($prom_buckets_ms) / 1000.0),*];
+        let $opts = $opts.with_buckets(&buckets);
+    };
+    (@opts $opts:ident, cfg_attr($pred:meta, $($attr:tt)*)) => {
+        #[cfg($pred)]
+        $crate::__composite_metric_field!(@opts $opts, $($attr)*);
+    };
+    (@opts $opts:ident, $($attr:tt)*) => {};
+
+    (
+        @async_init $opts:tt $field_name:ident: [$($field_ty:tt)+],
+        $(#[$($field_attr:tt)*])*
+    ) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @async_registry_init }
+            { @metric_init $opts &[], &$crate::OptsTemplate::new(), &|_| None, &|_| None, $field_name: [$($field_ty)+], $(#[$($field_attr)*])* }
+        }
+    };
+    (@async_registry_init) => {
+        ::std::compile_error!("`#[registry]` fields are not supported with `#[async_register]`");
+    };
+    (@async_register $self:ident, $registry:ident, $field_name:ident, $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_reregister }
+            { @metric_async_register $self, $registry, $field_name }
+        }
+    };
+    (@metric_async_register $self:ident, $registry:ident, $metric_name:ident) => {
+        $crate::AsyncRegistry::register(
+            $registry,
+            ::std::boxed::Box::new(::std::clone::Clone::clone(&$self.$metric_name)),
+        )
+        .await?;
+    };
+
+    // Initializes the metric after construction, e.g. pre-initializing label values.
+    (@post_init $metric_name:ident, labels_from = $label_enum:ty) => {
+        for value in <$label_enum as $crate::LabelEnum>::VALUES {
+            $metric_name.with_label_values(&[value]);
+        }
+    };
+    (@post_init $metric_name:ident, cfg_attr($pred:meta, $($attr:tt)*)) => {
+        #[cfg($pred)]
+        $crate::__composite_metric_field!(@post_init $metric_name, $($attr)*);
+    };
+    (@post_init $metric_name:ident, $($attr:tt)*) => {};
+
+    (@reregister $self:ident, $registry:ident, $field_name:ident, $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_reregister }
+            { @metric_reregister $self, $registry, $field_name }
+        }
+    };
+    (@registry_reregister) => {};
+    (@try_register $self:ident, $registry:ident, $field_name:ident, $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_reregister }
+            { @metric_try_register $self, $registry, $field_name }
+        }
+    };
+    (@metric_try_register $self:ident, $registry:ident, $metric_name:ident) => {
+        $crate::Registrar::register_collector(
+            $registry,
+            ::std::boxed::Box::new(::std::clone::Clone::clone(&$self.$metric_name)),
+        )
+            .map_err(|err| {
+                $crate::Error::new(
+                    "register",
+                    $crate::__private::field_name(::std::stringify!($metric_name)),
+                    err,
+                )
+            })?;
+    };
+    (@metric_reregister $self:ident, $registry:ident, $metric_name:ident) => {
+        $crate::Registrar::register_collector(
+            $registry,
+            ::std::boxed::Box::new(::std::clone::Clone::clone(&$self.$metric_name)),
+        )
+            .map_err(|err| {
+                $crate::__private::register_error(
+                    err,
+                    $crate::Registrar::registry($registry),
+                    $crate::__private::field_name(::std::stringify!($metric_name)),
+                    &$self.$metric_name,
+                )
+            })?;
+    };
+
+    (
+        @catalog $catalog:ident, $field_name:ident: [$($field_ty:tt)+],
+        $(#[$($field_attr:tt)*])*
+    ) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_catalog }
+            { @metric_catalog $catalog, [$($field_ty)+], $(#[$($field_attr)*])* }
+        }
+    };
+    (@registry_catalog) => {};
+    (@metric_catalog $catalog:ident, $metric_ty:tt, $(#[$($metric_attr:tt)*])*) => {
+        $(
+            $crate::__composite_metric_field!(@catalog_entry $catalog, $metric_ty, $($metric_attr)*);
+        )*
+    };
+    (@catalog_entry $catalog:ident, $metric_ty:tt, name = $prom_name:expr) => {
+        $catalog.push(($prom_name, $crate::__composite_metric_field!(@kind_str $metric_ty)));
+    };
+    (@catalog_entry $catalog:ident, $metric_ty:tt, dotted_name = $prom_name:expr) => {
+        $catalog.push((
+            $crate::__composite_metric_field!(@dotted_name $prom_name),
+            $crate::__composite_metric_field!(@kind_str $metric_ty),
+        ));
+    };
+    (@catalog_entry $catalog:ident, $metric_ty:tt, cfg_attr($pred:meta, $($attr:tt)*)) => {
+        #[cfg($pred)]
+        $crate::__composite_metric_field!(@catalog_entry $catalog, $metric_ty, $($attr)*);
+    };
+    (@catalog_entry $catalog:ident, $metric_ty:tt, $($attr:tt)*) => {};
+
+    // Describes a metric field for `dashboard_spec`, skipping `#[registry]` fields.
+    (
+        @dashboard_spec $opts:tt $metrics:ident, $field_name:ident: $field_ty:tt,
+        $(#[$($field_attr:tt)*])*
+    ) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_dashboard_spec }
+            { @metric_dashboard_spec $opts $metrics, $field_ty, $(#[$($field_attr)*])* }
+        }
+    };
+    (@registry_dashboard_spec) => {};
+    (@metric_dashboard_spec $opts:tt $metrics:ident, $metric_ty:tt, $(#[$($metric_attr:tt)*])*) => {{
+        $crate::__composite_metric_opt!(@default_desc $opts prom_desc, &[]);
+        #[allow(unused_variables)]
+        let labels: &[&str] = &[];
+        $(
+            $crate::__composite_metric_field!(@name_desc prom_name, prom_desc, &[], $($metric_attr)*);
+            $crate::__composite_metric_field!(@spec_labels labels, $($metric_attr)*);
+        )*
+        $metrics.push($crate::__private::metric_spec(
+            prom_name,
+            $crate::__composite_metric_field!(@kind_str $metric_ty),
+            &prom_desc,
+            labels,
+        ));
+    }};
+    (@spec_labels $labels:ident, labels = $prom_labels:expr) => {
+        let $labels: &[&str] = &$prom_labels[..];
+    };
+    (@spec_labels $labels:ident, labels_from = $label_enum:ty) => {
+        let $labels: &[&str] = &[<$label_enum as $crate::LabelEnum>::LABEL];
+    };
+    (@spec_labels $labels:ident, cfg_attr($pred:meta, $($attr:tt)*)) => {
+        #[cfg($pred)]
+        $crate::__composite_metric_field!(@spec_labels $labels, $($attr)*);
+    };
+    (@spec_labels $labels:ident, $($attr:tt)*) => {};
+
+    // The exposition type name, dispatched on the last segment of the metric type path.
+    (@non_positive_buckets $buckets:ident, $metric_name:ident: [$($seg:ident)? :: $($rest:tt)+]) => {
+        $crate::__composite_metric_field!(@non_positive_buckets $buckets, $metric_name: [$($rest)+]);
+    };
+    (@non_positive_buckets $buckets:ident, $metric_name:ident: [Histogram]) => {
+        $buckets.push(stringify!($metric_name), &$metric_name);
+    };
+    (@non_positive_buckets $buckets:ident, $metric_name:ident: [$($other:tt)*]) => {};
+
+    (@kind_str [$($seg:ident)? :: $($rest:tt)+]) => {
+        $crate::__composite_metric_field!(@kind_str [$($rest)+])
+    };
+    (@kind_str [Counter]) => { "counter" };
+    (@kind_str [IntCounter]) => { "counter" };
+    (@kind_str [CounterVec]) => { "counter" };
+    (@kind_str [IntCounterVec]) => { "counter" };
+    (@kind_str [GenericCounter $($gen:tt)*]) => { "counter" };
+    (@kind_str [GenericCounterVec $($gen:tt)*]) => { "counter" };
+    (@kind_str [Gauge]) => { "gauge" };
+    (@kind_str [IntGauge]) => { "gauge" };
+    (@kind_str [GaugeVec]) => { "gauge" };
+    (@kind_str [IntGaugeVec]) => { "gauge" };
+    (@kind_str [GenericGauge $($gen:tt)*]) => { "gauge" };
+    (@kind_str [GenericGaugeVec $($gen:tt)*]) => { "gauge" };
+    (@kind_str [TimestampedGauge]) => { "gauge" };
+    (@kind_str [Histogram]) => { "histogram" };
+    (@kind_str [HistogramVec]) => { "histogram" };
+    (@kind_str [$($other:tt)*]) => { "untyped" };
+
+    // Checks the label count against the `#[max_labels]` of the struct.
+    (@max_labels $opts:tt $field_name:ident) => {};
+    (@max_labels $opts:tt $field_name:ident #[labels = $labels:expr] $($rest:tt)*) => {
+        $crate::__composite_metric_opt!(@max_labels $opts $field_name $labels);
+        $crate::__composite_metric_field!(@max_labels $opts $field_name $($rest)*);
+    };
+    (
+        @max_labels $opts:tt $field_name:ident
+        #[cfg_attr($pred:meta, labels = $labels:expr)] $($rest:tt)*
+    ) => {
+        #[cfg($pred)]
+        $crate::__composite_metric_opt!(@max_labels $opts $field_name $labels);
+        $crate::__composite_metric_field!(@max_labels $opts $field_name $($rest)*);
+    };
+    (@max_labels $opts:tt $field_name:ident #[$($attr:tt)*] $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@max_labels $opts $field_name $($rest)*);
+    };
+
+    // Collects the metric fields, skipping `#[registry]` fields, into the `<Struct>MetricId` enum.
+    (@metric_id $v:vis $name:ident $params:tt $args:tt [$($ids:ident)*] []) => {
+        $crate::__composite_metric_field!(@metric_id_enum $v $name $params $args $($ids)*);
+    };
+    (
+        @metric_id $v:vis $name:ident $params:tt $args:tt [$($ids:ident)*]
+        [[$field_name:ident $($field_attr:tt)*] $($rest:tt)*]
+    ) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$($field_attr)*]
+            { @metric_id $v $name $params $args [$($ids)*] [$($rest)*] }
+            { @metric_id $v $name $params $args [$($ids)* $field_name] [$($rest)*] }
+        }
+    };
+    (
+        @metric_id_enum $v:vis $name:ident [$($params:tt)*] [$($args:tt)*]
+        $($metric_name:ident)*
+    ) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Identifies a metric of [`", stringify!($name), "`], see `", stringify!($name), "::metric`.")]
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+            #[allow(dead_code, clippy::enum_variant_names)]
+            $v enum [<$name MetricId>] {
+                $(
+                    #[doc = concat!("The `", stringify!($metric_name), "` field.")]
+                    [<$metric_name:camel>],
+                )*
+            }
+
+            impl<$($params)*> $name<$($args)*> {
+                /// Returns the metric of the given id as collector, e.g. for generic inspection.
+                #[allow(dead_code)]
+                $v fn metric(&self, which: [<$name MetricId>]) -> &dyn ::prometheus::core::Collector {
+                    match which {
+                        $([<$name MetricId>]::[<$metric_name:camel>] => &self.$metric_name,)*
+                    }
+                }
+            }
+        }
+    };
+
+    // Emits a struct binding the literal labels of a field by name, and `<field>_with_labels`
+    // taking it.
+    (
+        @labels_structs $v:vis $name:ident $params:tt $args:tt
+        [$([$field_name:ident: $field_ty:tt $($field_attr:tt)*])+]
+    ) => {
+        $(
+            $crate::__composite_metric_field!(
+                @labels_struct $v $name $params $args $field_name: $field_ty, $($field_attr)*
+            );
+        )+
+    };
+    (@labels_struct $v:vis $name:ident $params:tt $args:tt $field_name:ident: $field_ty:tt,) => {};
+    (
+        @labels_struct $v:vis $name:ident $params:tt $args:tt $field_name:ident: $field_ty:tt,
+        #[labels = [$($label:tt),+ $(,)?]] $($rest:tt)*
+    ) => {
+        $crate::__composite_metric_field! {
+            @if_ident_labels [$($label),+] {
+                $crate::__composite_metric_field!(
+                    @labels_items $v $name $params $args $field_name: $field_ty, $($label),+
+                );
+            }
+        }
+        $crate::__composite_metric_field!(
+            @labels_struct $v $name $params $args $field_name: $field_ty, $($rest)*
+        );
+    };
+    (
+        @labels_struct $v:vis $name:ident $params:tt $args:tt $field_name:ident: $field_ty:tt,
+        #[cfg_attr($pred:meta, labels = [$($label:tt),+ $(,)?])] $($rest:tt)*
+    ) => {
+        #[cfg($pred)]
+        $crate::__composite_metric_field!(
+            @labels_struct $v $name $params $args $field_name: $field_ty, #[labels = [$($label),+]]
+        );
+        $crate::__composite_metric_field!(
+            @labels_struct $v $name $params $args $field_name: $field_ty, $($rest)*
+        );
+    };
+    (
+        @labels_struct $v:vis $name:ident $params:tt $args:tt $field_name:ident: $field_ty:tt,
+        #[$($attr:tt)*] $($rest:tt)*
+    ) => {
+        $crate::__composite_metric_field!(
+            @labels_struct $v $name $params $args $field_name: $field_ty, $($rest)*
+        );
+    };
+    (
+        @labels_items $v:vis $name:ident [$($params:tt)*] [$($args:tt)*]
+        $field_name:ident: [$($field_ty:tt)+], $($label:literal),+
+    ) => {
+        $crate::__private::paste! {
+            #[doc = concat!("The label values of `", stringify!($name), "::", stringify!($field_name), "`, bound by name.")]
+            #[derive(Clone, Copy, Debug)]
+            #[allow(dead_code)]
+            $v struct [<$name $field_name:camel Labels>]<'a> {
+                $(
+                    #[doc = concat!("The value of the `", $label, "` label.")]
+                    $v [<$label>]: &'a str,
+                )+
+            }
+
+            impl<$($params)*> $name<$($args)*> {
+                #[doc = concat!("Returns the `", stringify!($field_name), "` series of the given labels, bound by name.")]
+                #[allow(dead_code)]
+                $v fn [<$field_name _with_labels>](
+                    &self,
+                    labels: [<$name $field_name:camel Labels>]<'_>,
+                ) -> <$($field_ty)+ as $crate::__private::VecChild>::Child {
+                    $crate::__private::VecChild::child(&self.$field_name, &[$(labels.[<$label>]),+])
+                }
+            }
+        }
+    };
+
+    // Emits the tokens only if all labels are literals that are valid field names, i.e. no
+    // keywords. The labels are matched as `tt`, as forwarded literals can't be compared.
+    (@if_ident_labels [] { $($then:tt)* }) => {
+        $($then)*
+    };
+    (@if_ident_labels ["as" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["async" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["await" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["break" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["const" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["continue" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["crate" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["dyn" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["else" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["enum" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["extern" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["false" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["fn" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["for" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["gen" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["if" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["impl" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["in" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["let" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["loop" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["match" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["mod" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["move" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["mut" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["pub" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["ref" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["return" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["self" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["Self" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["static" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["struct" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["super" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["trait" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["true" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["type" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["unsafe" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["use" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["where" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["while" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["abstract" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["become" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["box" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["do" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["final" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["macro" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["override" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["priv" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["try" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["typeof" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["unsized" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["virtual" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["yield" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels ["_" $($rest:tt)*] $then:tt) => {};
+    (@if_ident_labels [$label:literal $(, $($rest:tt)*)?] $then:tt) => {
+        $crate::__composite_metric_field!(@if_ident_labels [$($($rest)*)?] $then);
+    };
+    (@if_ident_labels [$($label:tt)*] $then:tt) => {};
+
+    (@collect_into $self:ident, $out:ident, $field_name:ident, $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_collect_into }
+            { @metric_collect_into $self, $out, $field_name }
+        }
+    };
+    (@registry_collect_into) => {};
+
+    (@fq_names $self:ident, $names:ident, $field_name:ident, $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_fq_names }
+            { @metric_fq_names $self, $names, $field_name }
+        }
+    };
+    (@registry_fq_names) => {};
+
+    (@collectors $self:ident, $collectors:ident, $field_name:ident, $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_collectors }
+            { @metric_collectors $self, $collectors, $field_name }
+        }
+    };
+    (@registry_collectors) => {};
+
+    (@count $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*] { @registry_count } { @metric_count }
+        }
+    };
+    (@registry_count) => { 0 };
+    (@metric_count) => { 1 };
+
+    (@descs $self:ident, $descs:ident, $field_name:ident, $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_descs }
+            { @metric_descs $self, $descs, $field_name }
+        }
+    };
+    (@registry_descs) => {};
+    (@metric_descs $self:ident, $descs:ident, $metric_name:ident) => {
+        $descs.extend(::prometheus::core::Collector::desc(&$self.$metric_name));
+    };
+    (@metric_collectors $self:ident, $collectors:ident, $metric_name:ident) => {
+        $collectors.push(::std::boxed::Box::new(::std::clone::Clone::clone(&$self.$metric_name)));
+    };
+    (@metric_fq_names $self:ident, $names:ident, $metric_name:ident) => {
+        $names.extend(
+            ::prometheus::core::Collector::desc(&$self.$metric_name)
+                .into_iter()
+                .map(|desc| desc.fq_name.as_str()),
+        );
+    };
+    (@metric_collect_into $self:ident, $out:ident, $metric_name:ident) => {
+        $out.extend(::prometheus::core::Collector::collect(&$self.$metric_name));
+    };
+
+    (@values_eq $self:ident, $other:ident, $field_name:ident, $(#[$($field_attr:tt)*])*) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_values_eq }
+            { @metric_values_eq $self, $other, $field_name }
+        }
+    };
+    (@registry_values_eq) => {
+        true
+    };
+    (@metric_values_eq $self:ident, $other:ident, $metric_name:ident) => {
+        $crate::__private::values_eq(&$self.$metric_name, &$other.$metric_name)
+    };
+
+    (
+        @counter_snapshot $self:ident, $counters:ident, $field_name:ident: [$($field_ty:tt)+],
+        $(#[$($field_attr:tt)*])*
+    ) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_counter_snapshot }
+            { @counter_kind $self, $counters, $field_name: [$($field_ty)+] }
+        }
+    };
+    (@registry_counter_snapshot) => {};
+    (@counter_kind $self:ident, $counters:ident, $metric_name:ident: [$($seg:ident)? :: $($rest:tt)+]) => {
+        $crate::__composite_metric_field!(@counter_kind $self, $counters, $metric_name: [$($rest)+]);
+    };
+    (@counter_kind $self:ident, $counters:ident, $metric_name:ident: [Counter]) => {
+        $crate::__composite_metric_field!(@counter_push $self, $counters, $metric_name);
+    };
+    (@counter_kind $self:ident, $counters:ident, $metric_name:ident: [IntCounter]) => {
+        $crate::__composite_metric_field!(@counter_push $self, $counters, $metric_name);
+    };
+    (@counter_kind $self:ident, $counters:ident, $metric_name:ident: [CounterVec]) => {
+        $crate::__composite_metric_field!(@counter_push $self, $counters, $metric_name);
+    };
+    (@counter_kind $self:ident, $counters:ident, $metric_name:ident: [IntCounterVec]) => {
+        $crate::__composite_metric_field!(@counter_push $self, $counters, $metric_name);
+    };
+    (@counter_kind $self:ident, $counters:ident, $metric_name:ident: [GenericCounter $($gen:tt)*]) => {
+        $crate::__composite_metric_field!(@counter_push $self, $counters, $metric_name);
+    };
+    (@counter_kind $self:ident, $counters:ident, $metric_name:ident: [GenericCounterVec $($gen:tt)*]) => {
+        $crate::__composite_metric_field!(@counter_push $self, $counters, $metric_name);
+    };
+    (@counter_kind $self:ident, $counters:ident, $metric_name:ident: [$($other:tt)*]) => {};
+    (@counter_push $self:ident, $counters:ident, $metric_name:ident) => {
+        $counters.push((
+            $crate::__private::field_name(::std::stringify!($metric_name)),
+            $crate::__private::counter_total(&$self.$metric_name),
+        ));
+    };
+
+    (
+        @methods $opts:tt $v:vis, $field_name:ident: [$($field_ty:tt)+],
+        $(#[$($field_attr:tt)*])*
+    ) => {
+        $crate::__composite_metric_field! {
+            @registry_or_metric [$(#[$($field_attr)*])*]
+            { @registry_methods $opts $v, $field_name: [$($field_ty)+] }
+            { @metric_methods $opts $v, $field_name: [$($field_ty)+], $(#[$($field_attr)*])* }
+        }
+    };
+    (@registry_methods $opts:tt $v:vis, $field_name:ident: [$($field_ty:tt)+]) => {
+        $crate::__composite_metric_opt! {
+            @getter $opts [] $v fn $field_name (&self) -> &$($field_ty)+ {
+                &self.$field_name
+            }
+        }
+
+        /// Creates a new registry and registers all metrics to it.
+        #[allow(dead_code)]
+        $v fn new() -> ::prometheus::Result<Self> {
+            Self::register(&::prometheus::Registry::new())
+        }
+
+        /// Gathers all metrics from the stored registry.
+        #[allow(dead_code)]
+        $v fn gather(&self) -> ::std::vec::Vec<::prometheus::proto::MetricFamily> {
+            self.$field_name.gather()
+        }
+    };
+    (
+        @metric_methods $opts:tt $v:vis, $metric_name:ident: [$($metric_ty:tt)+],
+        $(#[$($metric_attr:tt)*])*
+    ) => {
+        $crate::__composite_metric_field! {
+            @accessor [] {
+                $opts $v fn $metric_name (&self) -> &$($metric_ty)+ {
+                    $crate::__composite_metric_opt!(@if lazy_register $opts {
+                        let _ = self.ensure_registered();
+                    });
+                    &self.$metric_name
+                }
+            }
+            $(#[$($metric_attr)*])*
+        }
+
+        $crate::__private::paste! {
+            $crate::__composite_metric_opt!(@if enabled_gate $opts {
+                #[doc = concat!("Returns `", stringify!($metric_name), "` if recording is enabled, see `set_enabled`.")]
+                #[allow(dead_code)]
+                $v fn [<$metric_name _if_enabled>](&self) -> ::std::option::Option<&$($metric_ty)+> {
+                    if !self.is_enabled() {
+                        return ::std::option::Option::None;
+                    }
+                    $crate::__composite_metric_opt!(@if lazy_register $opts {
+                        let _ = self.ensure_registered();
+                    });
+                    ::std::option::Option::Some(&self.$metric_name)
+                }
+            });
+
+            #[doc = concat!("Returns the fully-qualified name of `", stringify!($metric_name), "`.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _name>](&self) -> &str {
+                &::prometheus::core::Collector::desc(&self.$metric_name)[0].fq_name
+            }
+
+            #[doc = concat!("Encodes `", stringify!($metric_name), "` in the text exposition format.")]
+            ///
+            /// Returns an empty string if the metric can't be encoded.
+            #[allow(dead_code)]
+            $v fn [<$metric_name _display>](&self) -> ::std::string::String {
+                ::prometheus::TextEncoder::new()
+                    .encode_to_string(&::prometheus::core::Collector::collect(&self.$metric_name))
+                    .unwrap_or_default()
+            }
+        }
+
+        $crate::__composite_metric_field!(
+            @attrs_methods $v, $metric_name: [$($metric_ty)+], $(#[$($metric_attr)*])*
+        );
+
+        $crate::__composite_metric_field!(@kind_methods $v, $metric_name: [$($metric_ty)+]);
+    };
+
+    // Methods enabled by field attributes.
+    (@attrs_methods $v:vis, $metric_name:ident: $metric_ty:tt, $(#[$($metric_attr:tt)*])*) => {
+        $(
+            $crate::__composite_metric_field!(
+                @attr_methods $v, $metric_name: $metric_ty, $($metric_attr)*
+            );
+        )*
+    };
+    (@attr_methods $v:vis, $metric_name:ident: $metric_ty:tt, labels_from = $label_enum:ty) => {
+        $crate::__composite_metric_field!(@labels_from_methods $v, $metric_name: $metric_ty, $label_enum);
+    };
+    (
+        @attr_methods $v:vis, $metric_name:ident: [$($metric_ty:tt)+],
+        label_patterns = [$($pattern:literal),+ $(,)?]
+    ) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Returns the `", stringify!($metric_name), "` series of the given label values, if they match the `#[label_patterns]`.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _checked>](
+                &self,
+                values: &[&str],
+            ) -> ::prometheus::Result<<$($metric_ty)+ as $crate::__private::VecChild>::Child> {
+                static PATTERNS: ::std::sync::OnceLock<
+                    ::std::vec::Vec<$crate::__private::LabelPattern>,
+                > = ::std::sync::OnceLock::new();
+                let patterns = PATTERNS.get_or_init(|| {
+                    ::std::vec![$($crate::__private::LabelPattern::new($pattern)),+]
+                });
+                for (value, pattern) in values.iter().zip(patterns) {
+                    if !pattern.matches(value) {
+                        return Err(::prometheus::Error::Msg(format!(
+                            "label value {value:?} of {} doesn't match {:?}",
+                            stringify!($metric_name),
+                            pattern.as_str(),
+                        )));
+                    }
+                }
+                $crate::__private::VecChild::try_child(&self.$metric_name, values)
+            }
+        }
+    };
+    (@attr_methods $v:vis, $metric_name:ident: $metric_ty:tt, pair_with = $counter:ident) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Observes `v` in `", stringify!($metric_name), "` and increments `", stringify!($counter), "` with the same label values.")]
+            ///
+            /// Label values are only given for vector metrics, and panic if their number
+            /// doesn't match.
+            #[allow(dead_code)]
+            #[track_caller]
+            $v fn [<$metric_name _record>](&self, values: &[&str], v: f64) {
+                $crate::__private::Observe::observe_with(&self.$metric_name, values, v);
+                $crate::__private::Increment::inc_with(&self.$counter, values);
+            }
+        }
+    };
+    (@attr_methods $v:vis, $metric_name:ident: $metric_ty:tt, slo_buckets = $slo:tt) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Returns the fraction of `", stringify!($metric_name), "` observations within each SLO boundary.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _slo_fractions>](&self) -> ::std::vec::Vec<(f64, f64)> {
+                ::prometheus::core::Collector::collect(&self.$metric_name)
+                    .first()
+                    .map($crate::slo_fractions)
+                    .unwrap_or_default()
+            }
+        }
+    };
+    (@attr_methods $v:vis, $metric_name:ident: $metric_ty:tt, cfg_attr($pred:meta, $($attr:tt)*)) => {
+        #[cfg($pred)]
+        $crate::__composite_metric_field!(@attr_methods $v, $metric_name: $metric_ty, $($attr)*);
+    };
+    (@attr_methods $v:vis, $metric_name:ident: $metric_ty:tt, $($attr:tt)*) => {};
+    (
+        @labels_from_methods $v:vis, $metric_name:ident: [$($metric_ty:tt)+],
+        $label_enum:ty
+    ) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Returns the `", stringify!($metric_name), "` series of the given label value.")]
+            #[allow(dead_code)]
+            #[track_caller]
+            $v fn [<$metric_name _with>](
+                &self,
+                value: $label_enum,
+            ) -> <$($metric_ty)+ as $crate::__private::VecChild>::Child {
+                $crate::__private::VecChild::child(
+                    &self.$metric_name,
+                    &[$crate::LabelEnum::as_label(&value)],
+                )
+            }
+        }
+    };
+
+    // Rejects field attributes unknown to the macro with `#[strict]`.
+    (@strict $field_name:ident) => {};
+    (@strict $field_name:ident #[cfg_attr($pred:meta, $($attr:tt)*)] $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@strict $field_name #[$($attr)*] $($rest)*);
+    };
+    (@strict $field_name:ident #[$key:ident $($attr:tt)*] $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@strict_key $key $field_name);
+        $crate::__composite_metric_field!(@strict $field_name $($rest)*);
+    };
+    (@strict $field_name:ident #[$($attr:tt)*] $($rest:tt)*) => {
+        ::std::compile_error!(::std::concat!(
+            "unknown attribute `#[", ::std::stringify!($($attr)*), "]` on field `",
+            ::std::stringify!($field_name), "`"
+        ));
+        $crate::__composite_metric_field!(@strict $field_name $($rest)*);
+    };
+    (@strict_key name $field_name:ident) => {};
+    (@strict_key dotted_name $field_name:ident) => {};
+    (@strict_key desc $field_name:ident) => {};
+    (@strict_key labels $field_name:ident) => {};
+    (@strict_key labels_from $field_name:ident) => {};
+    (@strict_key buckets $field_name:ident) => {};
+    (@strict_key buckets_ms $field_name:ident) => {};
+    (@strict_key slo_buckets $field_name:ident) => {};
+    (@strict_key label_patterns $field_name:ident) => {};
+    (@strict_key pair_with $field_name:ident) => {};
+    (@strict_key example $field_name:ident) => {};
+    (@strict_key registry $field_name:ident) => {};
+    (@strict_key doc $field_name:ident) => {};
+    (@strict_key allow $field_name:ident) => {};
+    (@strict_key deprecated $field_name:ident) => {};
+    (@strict_key must_use $field_name:ident) => {};
+    (@strict_key $key:ident $field_name:ident) => {
+        ::std::compile_error!(::std::concat!(
+            "unknown attribute `", ::std::stringify!($key), "` on field `",
+            ::std::stringify!($field_name), "`"
+        ));
+    };
+
+    // Emits the accessor with all field attributes not understood by the macro, e.g. docs.
+    (@accessor [$($kept:tt)*] { $opts:tt $($accessor:tt)* }) => {
+        $crate::__composite_metric_opt! {
+            @getter $opts [$($kept)*] $($accessor)*
+        }
+    };
+    (
+        @accessor [$($kept:tt)*] $accessor:tt
+        #[cfg_attr($pred:meta, $key:ident $($attr:tt)*)] $($rest:tt)*
+    ) => {
+        $crate::__composite_metric_field!(
+            @accessor_attr $key [$($kept)*] [#[cfg_attr($pred, $key $($attr)*)]] $accessor $($rest)*
+        );
+    };
+    (@accessor [$($kept:tt)*] $accessor:tt #[example = $example:literal] $($rest:tt)*) => {
+        $crate::__composite_metric_field!(
+            @accessor [
+                $($kept)*
+                #[doc = ""]
+                #[doc = "# Examples"]
+                #[doc = ""]
+                #[doc = "```ignore"]
+                #[doc = $example]
+                #[doc = "```"]
+            ] $accessor $($rest)*
+        );
+    };
+    (@accessor [$($kept:tt)*] $accessor:tt #[$key:ident $($attr:tt)*] $($rest:tt)*) => {
+        $crate::__composite_metric_field!(
+            @accessor_attr $key [$($kept)*] [#[$key $($attr)*]] $accessor $($rest)*
+        );
+    };
+    (@accessor_attr name [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr dotted_name [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr desc [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr labels [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr buckets [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr labels_from [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr buckets_ms [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr slo_buckets [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr label_patterns [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr pair_with [$($kept:tt)*] $attr:tt $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)*] $accessor $($rest)*);
+    };
+    (@accessor_attr $key:ident [$($kept:tt)*] [$($attr:tt)*] $accessor:tt $($rest:tt)*) => {
+        $crate::__composite_metric_field!(@accessor [$($kept)* $($attr)*] $accessor $($rest)*);
+    };
+
+    // Type specific methods, dispatched on the last segment of the metric type path.
+    (@kind_methods $v:vis, $metric_name:ident: [$($seg:ident)? :: $($rest:tt)+]) => {
+        $crate::__composite_metric_field!(@kind_methods $v, $metric_name: [$($rest)+]);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [Gauge]) => {
+        $crate::__composite_metric_field!(@gauge_methods $v, $metric_name: []);
+        $crate::__composite_metric_field!(@float_gauge_methods $v, $metric_name);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [IntGauge]) => {
+        $crate::__composite_metric_field!(
+            @gauge_methods $v, $metric_name: [<::prometheus::core::AtomicI64>]
+        );
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [GenericGauge $($gen:tt)*]) => {
+        $crate::__composite_metric_field!(@gauge_methods $v, $metric_name: [$($gen)*]);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [Counter]) => {
+        $crate::__composite_metric_field!(@counter_methods $v, $metric_name);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [IntCounter]) => {
+        $crate::__composite_metric_field!(@counter_methods $v, $metric_name);
+        $crate::__composite_metric_field!(@int_counter_methods $v, $metric_name);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [GenericCounter $($gen:tt)*]) => {
+        $crate::__composite_metric_field!(@counter_methods $v, $metric_name);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [CounterVec]) => {
+        $crate::__composite_metric_field!(@counter_vec_methods $v, $metric_name);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [IntCounterVec]) => {
+        $crate::__composite_metric_field!(@int_vec_methods $v, $metric_name: u64);
+        $crate::__composite_metric_field!(@counter_vec_methods $v, $metric_name);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [IntGaugeVec]) => {
+        $crate::__composite_metric_field!(@int_vec_methods $v, $metric_name: i64);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [HistogramVec]) => {
+        $crate::__composite_metric_field!(@histogram_vec_methods $v, $metric_name);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [Histogram]) => {
+        $crate::__composite_metric_field!(@histogram_methods $v, $metric_name);
+        $crate::__composite_metric_test_util!(@histogram_methods $v, $metric_name);
+    };
+    (@kind_methods $v:vis, $metric_name:ident: [$($other:tt)*]) => {};
+
+    (@counter_vec_methods $v:vis, $metric_name:ident) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Returns the sum of all `", stringify!($metric_name), "` series.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _total>](&self) -> f64 {
+                $crate::__private::counter_total(&self.$metric_name)
+            }
+        }
+    };
+
+    (@int_vec_methods $v:vis, $metric_name:ident: $value_ty:ty) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Returns the value of the `", stringify!($metric_name), "` series with the given label values.")]
+            ///
+            /// A series that doesn't exist yet reads as zero and isn't created, so reading
+            /// doesn't add series to the exposition. Panics if the number of label values
+            /// doesn't match.
+            #[allow(dead_code)]
+            #[track_caller]
+            $v fn [<$metric_name _get>](&self, values: &[&str]) -> $value_ty {
+                $crate::__private::series_value(&self.$metric_name, values)
+                    .map_or(0, |value| value as $value_ty)
+            }
+        }
+    };
+
+    (@histogram_methods $v:vis, $metric_name:ident) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Returns the cumulative `(upper_bound, count)` buckets of `", stringify!($metric_name), "`.")]
+            ///
+            /// The last bucket is `+Inf`, counting all observations.
+            #[allow(dead_code)]
+            $v fn [<$metric_name _buckets>](&self) -> ::std::vec::Vec<(f64, u64)> {
+                ::prometheus::core::Collector::collect(&self.$metric_name)
+                    .first()
+                    .map($crate::__private::cumulative_buckets)
+                    .unwrap_or_default()
+            }
+
+            #[doc = concat!("Returns whether `", stringify!($metric_name), "` has any observations, e.g. for health checks.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _is_observed>](&self) -> bool {
+                self.$metric_name.get_sample_count() > 0
+            }
+
+            #[doc = concat!("Observes `v` in `", stringify!($metric_name), "`, rejecting NaN and infinite values.")]
+            ///
+            /// Negative values are rejected as well, unless a bucket bound is zero or negative.
+            #[allow(dead_code)]
+            $v fn [<$metric_name _observe_checked>](&self, v: f64) -> ::prometheus::Result<()> {
+                let negative_buckets = self
+                    .__non_positive_buckets
+                    .contains(stringify!($metric_name));
+                if !v.is_finite() || (v < 0.0 && !negative_buckets) {
+                    return Err(::prometheus::Error::Msg(format!(
+                        "invalid observation {v} for histogram {}",
+                        stringify!($metric_name)
+                    )));
+                }
+                self.$metric_name.observe(v);
+                Ok(())
+            }
+        }
+    };
+
+    (@histogram_vec_methods $v:vis, $metric_name:ident) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Returns the `", stringify!($metric_name), "` histogram with the given label values.")]
+            ///
+            /// The handle can be kept to observe repeatedly without looking up the labels
+            /// again. Panics if the number of label values doesn't match.
+            #[allow(dead_code)]
+            #[track_caller]
+            $v fn [<$metric_name _for>](&self, values: &[&str]) -> ::prometheus::Histogram {
+                $crate::__private::VecChild::child(&self.$metric_name, values)
+            }
+
+            #[doc = concat!("Returns whether any `", stringify!($metric_name), "` series has observations, e.g. for health checks.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _is_observed>](&self) -> bool {
+                ::prometheus::core::Collector::collect(&self.$metric_name)
+                    .iter()
+                    .flat_map(|family| family.get_metric())
+                    .any(|metric| metric.get_histogram().get_sample_count() > 0)
+            }
+        }
+    };
+
+    (@int_counter_methods $v:vis, $metric_name:ident) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Increments `", stringify!($metric_name), "` by `v`, capping at `u64::MAX`.")]
+            ///
+            /// The current value is read before incrementing, so concurrent increments in
+            /// between may still overflow.
+            #[allow(dead_code)]
+            $v fn [<$metric_name _inc_by_saturating>](&self, v: u64) {
+                let current = self.$metric_name.get();
+                self.$metric_name.inc_by(current.saturating_add(v) - current);
+            }
+        }
+    };
+
+    (@counter_methods $v:vis, $metric_name:ident) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Increments `", stringify!($metric_name), "` by `rate` for one in `rate` calls on average.")]
+            ///
+            /// This trades accuracy for less contention on hot counters, the expected total
+            /// matches the number of calls.
+            #[allow(dead_code)]
+            $v fn [<$metric_name _inc_sampled>](&self, rate: u32) {
+                $crate::__private::IncSampled::inc_sampled(&self.$metric_name, rate)
+            }
+        }
+    };
+
+    (@float_gauge_methods $v:vis, $metric_name:ident) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Sets `", stringify!($metric_name), "` to `v`, rejecting NaN and infinite values.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _set_checked>](&self, v: f64) -> ::prometheus::Result<()> {
+                if !v.is_finite() {
+                    return Err(::prometheus::Error::Msg(format!(
+                        "invalid value {v} for gauge {}",
+                        stringify!($metric_name)
+                    )));
+                }
+                self.$metric_name.set(v);
+                Ok(())
+            }
+        }
+    };
+
+    (@gauge_methods $v:vis, $metric_name:ident: [$($gen:tt)*]) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Sets `", stringify!($metric_name), "` to the current UNIX time in seconds.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _set_now>](&self) {
+                $crate::__private::SetNow::set_now(&self.$metric_name)
+            }
+
+            #[doc = concat!("Increments `", stringify!($metric_name), "` until the returned guard is dropped.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _guard>](&self) -> $crate::InFlightGuard $($gen)* {
+                $crate::InFlightGuard::new(self.$metric_name.clone())
+            }
+        }
+    };
+}
+
+/// Types that provide access to a prometheus [`Registry`], such as newtype wrappers.
+///
+/// The generated `register` methods accept any registry-like type, as it is a [`Registrar`].
+///
+/// ```
+/// use prometheus::{IntGauge, Registry};
+/// use prometheus_macros::{composite_metric, RegistryLike};
+///
+/// struct AppRegistry(Registry);
+///
+/// impl RegistryLike for AppRegistry {
+///     fn as_registry(&self) -> &Registry {
+///         &self.0
+///     }
+/// }
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "custom_gauge"]
+///         #[desc = "Example gauge metric"]
+///         custom_gauge: IntGauge,
+///     }
+/// }
+///
+/// let registry = AppRegistry(Registry::new());
+/// let metric = CompositeMetric::register(&registry).unwrap();
+/// ```
+pub trait RegistryLike {
+    /// Returns the underlying registry.
+    fn as_registry(&self) -> &Registry;
+}
+
+impl RegistryLike for Registry {
+    fn as_registry(&self) -> &Registry {
+        self
+    }
+}
+
+/// Receives the metrics of the generated `register` methods, e.g. a mock capturing them in
+/// tests.
+///
+/// All [`RegistryLike`] types register to their registry. Registrars without a registry give
+/// `#[registry]` fields a fresh one.
+///
+/// ```
+/// use std::sync::Mutex;
+///
+/// use prometheus::core::Collector;
+/// use prometheus::IntGauge;
+/// use prometheus_macros::{composite_metric, Registrar};
+///
+/// #[derive(Default)]
+/// struct MockRegistrar(Mutex<Vec<String>>);
+///
+/// impl Registrar for MockRegistrar {
+///     fn register_collector(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+///         let mut names = self.0.lock().unwrap();
+///         names.extend(collector.desc().iter().map(|desc| desc.fq_name.clone()));
+///         Ok(())
+///     }
+/// }
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "custom_gauge"]
+///         #[desc = "Example gauge metric"]
+///         custom_gauge: IntGauge,
+///     }
+/// }
+///
+/// let registrar = MockRegistrar::default();
+/// let metric = CompositeMetric::register(&registrar).unwrap();
+/// assert_eq!(*registrar.0.lock().unwrap(), ["custom_gauge"]);
+/// ```
+pub trait Registrar {
+    /// Registers a collector.
+    fn register_collector(&self, collector: Box<dyn Collector>) -> prometheus::Result<()>;
+
+    /// Returns the underlying registry, if any.
+    fn registry(&self) -> Option<&Registry> {
+        None
+    }
+}
+
+impl<T: RegistryLike + ?Sized> Registrar for T {
+    fn register_collector(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+        self.as_registry().register(collector)
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        Some(self.as_registry())
+    }
+}
+
+impl<T: RegistryLike + ?Sized> RegistryLike for &T {
+    fn as_registry(&self) -> &Registry {
+        (**self).as_registry()
+    }
+}
+
+impl<T: RegistryLike + ?Sized> RegistryLike for Box<T> {
+    fn as_registry(&self) -> &Registry {
+        (**self).as_registry()
+    }
+}
+
+impl<T: RegistryLike + ?Sized> RegistryLike for std::sync::Arc<T> {
+    fn as_registry(&self) -> &Registry {
+        (**self).as_registry()
+    }
+}
+
+/// Registries that register collectors asynchronously, e.g. behind an async lock.
+///
+/// Structs annotated with `#[async_register]` get a `register_async` method that awaits the
+/// registration of each metric.
+///
+/// ```
+/// use prometheus::core::Collector;
+/// use prometheus::{IntGauge, Registry};
+/// use prometheus_macros::{composite_metric, AsyncRegistry};
+///
+/// struct LockedRegistry(tokio::sync::Mutex<Registry>);
+///
+/// impl AsyncRegistry for LockedRegistry {
+///     async fn register(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+///         self.0.lock().await.register(collector)
+///     }
+/// }
+///
+/// composite_metric! {
+///     #[async_register]
+///     struct CompositeMetric {
+///         #[name = "custom_gauge"]
+///         #[desc = "Example gauge metric"]
+///         custom_gauge: IntGauge,
+///     }
+/// }
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let registry = LockedRegistry(tokio::sync::Mutex::new(Registry::new()));
+/// let metric = CompositeMetric::register_async(&registry).await.unwrap();
+/// metric.custom_gauge().set(420);
+/// # });
+/// ```
+pub trait AsyncRegistry {
+    /// Registers a collector.
+    fn register(
+        &self,
+        collector: Box<dyn Collector>,
+    ) -> impl Future<Output = prometheus::Result<()>> + Send;
+}
+
+impl AsyncRegistry for Registry {
+    fn register(
+        &self,
+        collector: Box<dyn Collector>,
+    ) -> impl Future<Output = prometheus::Result<()>> + Send {
+        std::future::ready(Registry::register(self, collector))
+    }
+}
+
+/// A more generic prometheus options that allow construction of both scalar and vector metrics.
+#[derive(Clone, Debug, Default)]
+pub struct Opts<'a> {
+    namespace: Option<&'a str>,
+    subsystem: Option<&'a str>,
+    name: Cow<'a, str>,
+    desc: Cow<'a, str>,
+    labels: Option<Cow<'a, [&'a str]>>,
+    const_labels: Option<Cow<'a, [(&'a str, &'a str)]>>,
+    buckets: Option<Cow<'a, [f64]>>,
+}
+
+impl<'a> Opts<'a> {
+    /// Create a new generic metric option based name, helper text and optional labels.
+    pub fn new(name: &'a str, desc: &'a str) -> Self {
+        Self {
+            name: name.into(),
+            desc: desc.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Creates options owning the name and helper text, e.g. for names built at runtime.
+    ///
+    /// ```
+    /// use prometheus::IntCounter;
+    /// use prometheus_macros::Opts;
+    ///
+    /// let plugin = "resize";
+    /// let opts = Opts::owned(format!("{plugin}_calls"), format!("Calls of {plugin}"));
+    /// let counter = IntCounter::try_from(opts).unwrap();
+    /// ```
+    pub fn owned(name: String, desc: String) -> Self {
+        Self {
+            name: name.into(),
+            desc: desc.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Attaches variable labels to the options, as required by vector metrics.
+    ///
+    /// The labels may be given as slice, array reference or owned `Vec`.
+    pub fn with_variable_labels(mut self, labels: impl Into<Cow<'a, [&'a str]>>) -> Self {
+        self.labels = Some(labels.into());
+        self
+    }
+
+    /// Attaches labels to the options.
+    #[deprecated(note = "use `with_variable_labels` instead")]
+    pub fn with_labels(self, labels: impl Into<Cow<'a, [&'a str]>>) -> Self {
+        self.with_variable_labels(labels)
+    }
+
+    /// Attaches const labels, which have the same value on all series of the metric.
+    pub fn with_const_labels(mut self, const_labels: &'a [(&'a str, &'a str)]) -> Self {
+        self.const_labels = Some(const_labels.into());
+        self
+    }
+
+    /// Applies the namespace, subsystem and const labels of a template, as far as it sets them.
+    pub fn with_template(mut self, template: &OptsTemplate<'a>) -> Self {
+        self.namespace = template.namespace.or(self.namespace);
+        self.subsystem = template.subsystem.or(self.subsystem);
+        if let Some(const_labels) = template.const_labels {
+            self.const_labels = Some(const_labels.into());
+        }
+        self
+    }
+
+    fn const_label_map(&self) -> HashMap<String, String> {
+        self.const_labels
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+            .collect()
+    }
+
+    /// Attaches buckets to the options.
+    pub fn with_buckets(mut self, buckets: &'a [f64]) -> Self {
+        self.buckets = Some(buckets.into());
+        self
+    }
+
+    /// Attaches buckets collected from an iterator to the options.
+    pub fn with_buckets_iter(mut self, buckets: impl IntoIterator<Item = f64>) -> Self {
+        self.buckets = Some(buckets.into_iter().collect::<Vec<_>>().into());
+        self
+    }
+
+    /// Attaches the [`DEFAULT_BUCKETS`](prometheus::DEFAULT_BUCKETS) multiplied by `factor`,
+    /// e.g. `10.0` for operations ten times slower than the default ladder suits.
+    ///
+    /// A factor that isn't positive and finite gives buckets that aren't increasing or finite,
+    /// which fails the conversion into a histogram.
+    pub fn with_scaled_default_buckets(self, factor: f64) -> Self {
+        self.with_buckets_iter(prometheus::DEFAULT_BUCKETS.iter().map(|b| b * factor))
+    }
+
+    /// Rejects variable labels that are also const labels.
+    fn check_label_overlap(&self) -> prometheus::Result<()> {
+        let labels = self.labels.as_deref().unwrap_or_default();
+        match self
+            .const_labels
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .find(|(name, _)| labels.contains(name))
+        {
+            Some((label, _)) => Err(prometheus::Error::Msg(format!(
+                "label {label} of {} is both a const and a variable label",
+                self.name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Rejects infinite, NaN and unordered buckets, prometheus appends the `+Inf` bucket itself.
+    fn check_buckets(&self) -> prometheus::Result<()> {
+        let buckets = self.buckets.as_deref();
+        if buckets.is_some_and(<[f64]>::is_empty) {
+            return Err(prometheus::Error::Msg(format!(
+                "empty buckets for {}, at least one bucket is required",
+                self.name
+            )));
+        }
+        let buckets = buckets.unwrap_or_default();
+        if let Some(bucket) = buckets.iter().find(|b| !b.is_finite()) {
+            return Err(prometheus::Error::Msg(format!(
+                "invalid bucket {bucket} for {}, buckets must be finite",
+                self.name
+            )));
+        }
+        if !__private::is_strictly_increasing(buckets) {
+            return Err(prometheus::Error::Msg(format!(
+                "invalid buckets for {}, buckets must be strictly increasing",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Runtime options applied to every metric of a composite metric, see `register_with_template`.
+///
+/// ```
+/// use prometheus::{IntCounter, Registry};
+/// use prometheus_macros::{composite_metric, OptsTemplate};
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "requests_total"]
+///         #[desc = "Number of requests"]
+///         requests: IntCounter,
+///     }
+/// }
+///
+/// let template = OptsTemplate::new()
+///     .with_namespace("app")
+///     .with_const_labels(&[("instance", "a")]);
+/// let metric = CompositeMetric::register_with_template(&Registry::new(), &template).unwrap();
+/// assert_eq!(metric.requests_name(), "app_requests_total");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OptsTemplate<'a> {
+    namespace: Option<&'a str>,
+    subsystem: Option<&'a str>,
+    const_labels: Option<&'a [(&'a str, &'a str)]>,
+}
+
+impl<'a> OptsTemplate<'a> {
+    /// Creates an empty template, which leaves the options unchanged.
+    pub const fn new() -> Self {
+        Self {
+            namespace: None,
+            subsystem: None,
+            const_labels: None,
+        }
+    }
+
+    /// Prefixes the metric names with the given namespace.
+    pub fn with_namespace(mut self, namespace: &'a str) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Prefixes the metric names with the given subsystem, after the namespace.
+    pub fn with_subsystem(mut self, subsystem: &'a str) -> Self {
+        self.subsystem = subsystem.into();
+        self
+    }
+
+    /// Attaches const labels to all metrics.
+    pub fn with_const_labels(mut self, const_labels: &'a [(&'a str, &'a str)]) -> Self {
+        self.const_labels = const_labels.into();
+        self
+    }
+}
+
+impl From<Opts<'_>> for PrometheusOpts {
+    /// Converts into prometheus options, carrying over namespace, subsystem, name, description
+    /// and const labels.
+    ///
+    /// Variable labels are passed separately when constructing vector metrics.
+    fn from(opts: Opts<'_>) -> Self {
+        let const_labels = opts.const_label_map();
+        PrometheusOpts::new(opts.name, opts.desc)
+            .namespace(opts.namespace.unwrap_or_default())
+            .subsystem(opts.subsystem.unwrap_or_default())
+            .const_labels(const_labels)
+    }
+}
+
+impl<'a> From<&'a PrometheusOpts> for Opts<'a> {
+    /// Converts from prometheus options, borrowing namespace, subsystem, name, description and
+    /// const labels, e.g. to migrate existing code incrementally.
+    ///
+    /// Variable labels are not part of the prometheus options, they must be set with
+    /// [`with_variable_labels`](Opts::with_variable_labels).
+    fn from(opts: &'a PrometheusOpts) -> Self {
+        let non_empty = |s: &'a String| (!s.is_empty()).then_some(s.as_str());
+        let const_labels = (!opts.const_labels.is_empty()).then(|| {
+            opts.const_labels
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect::<Vec<_>>()
+                .into()
+        });
+        Self {
+            namespace: non_empty(&opts.namespace),
+            subsystem: non_empty(&opts.subsystem),
+            const_labels,
+            ..Opts::new(&opts.name, &opts.help)
+        }
+    }
+}
+
+impl From<Opts<'_>> for HistogramOpts {
+    /// Converts into prometheus histogram options, carrying over namespace, subsystem, name,
+    /// description, const labels and buckets.
+    ///
+    /// Variable labels are passed separately when constructing vector metrics.
+    fn from(opts: Opts<'_>) -> Self {
+        let const_labels = opts.const_label_map();
+        let mut hist_opts = HistogramOpts::new(opts.name, opts.desc)
+            .namespace(opts.namespace.unwrap_or_default())
+            .subsystem(opts.subsystem.unwrap_or_default())
+            .const_labels(const_labels);
+        if let Some(buckets) = opts.buckets {
+            hist_opts.buckets = buckets.into_owned();
+        }
+        hist_opts
+    }
+}
+
+macro_rules! impl_try_from {
+    ($ident:ident, $opts:ident $(, $check:ident)?) => {
+        impl TryFrom<Opts<'_>> for $ident {
+            type Error = prometheus::Error;
+            fn try_from(opts: Opts<'_>) -> Result<Self, Self::Error> {
+                $(opts.$check()?;)?
+                <$ident>::with_opts(<$opts>::from(opts))
+            }
+        }
+    };
+}
+
+impl_try_from!(Counter, PrometheusOpts);
+impl_try_from!(IntCounter, PrometheusOpts);
+impl_try_from!(Histogram, HistogramOpts, check_buckets);
+
+/// Constructs gauges of any atomic, i.e. [`Gauge`](prometheus::Gauge),
+/// [`IntGauge`](prometheus::IntGauge) and gauges with custom [`Atomic`] backends.
+impl<P: Atomic> TryFrom<Opts<'_>> for GenericGauge<P> {
+    type Error = prometheus::Error;
+    fn try_from(opts: Opts<'_>) -> Result<Self, Self::Error> {
+        GenericGauge::with_opts(PrometheusOpts::from(opts))
+    }
+}
+
+impl<P: Atomic> TryFrom<&Opts<'_>> for GenericGauge<P> {
+    type Error = prometheus::Error;
+    fn try_from(opts: &Opts<'_>) -> Result<Self, Self::Error> {
+        Self::try_from(opts.clone())
+    }
+}
+
+macro_rules! impl_try_from_vec {
+    ($ident:ident, $opts:ident $(, $check:ident)?) => {
+        impl TryFrom<Opts<'_>> for $ident {
+            type Error = prometheus::Error;
+            fn try_from(mut opts: Opts<'_>) -> Result<Self, Self::Error> {
+                $(opts.$check()?;)?
+                opts.check_label_overlap()?;
+                let labels = opts.labels.take().ok_or_else(|| {
+                    prometheus::Error::Msg("vec requires one or more labels".to_owned())
+                })?;
+                <$ident>::new(<$opts>::from(opts), &labels)
+            }
+        }
+    };
+}
+
+impl_try_from_vec!(IntCounterVec, PrometheusOpts);
+impl_try_from_vec!(CounterVec, PrometheusOpts);
+impl_try_from_vec!(GaugeVec, PrometheusOpts);
+impl_try_from_vec!(IntGaugeVec, PrometheusOpts);
+impl_try_from_vec!(HistogramVec, HistogramOpts, check_buckets);
+
+macro_rules! impl_try_from_ref {
+    ($($ident:ident),+) => {
+        $(
+            impl TryFrom<&Opts<'_>> for $ident {
+                type Error = prometheus::Error;
+                fn try_from(opts: &Opts<'_>) -> Result<Self, Self::Error> {
+                    Self::try_from(opts.clone())
+                }
+            }
+        )+
+    };
+}
+
+impl_try_from_ref!(
+    Counter,
+    IntCounter,
+    Histogram,
+    IntCounterVec,
+    CounterVec,
+    GaugeVec,
+    IntGaugeVec,
+    HistogramVec,
+    TimestampedGauge
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use prometheus::core::Collector;
+    use prometheus::*;
+
+    fn parse_name(enc: &str) -> &str {
+        enc.lines()
+            .next()
+            .expect("mutliple lines")
+            .split(' ')
+            .nth(2)
+            .expect("description line")
+    }
+
+    fn parse_description(enc: &str) -> &str {
+        enc.lines()
+            .next()
+            .expect("mutliple lines")
+            .split(' ')
+            .nth(3)
+            .expect("description line")
+    }
+
+    fn parse_type(enc: &str) -> &str {
+        enc.lines()
+            .nth(1)
+            .expect("mutliple lines")
+            .split(' ')
+            .nth(3)
+            .expect("type line")
+    }
+
+    fn parse_labels(enc: &str) -> Vec<&str> {
+        let (_, s) = enc
+            .lines()
+            .nth(2)
+            .expect("mutliple lines")
+            .split_once('{')
+            .unwrap();
+        let (s, _) = s.split_once('}').unwrap();
+        s.split(',')
+            .filter_map(|s| {
+                let (l, _) = s.split_once('=')?;
+                Some(l)
+            })
+            .collect()
+    }
+
+    fn parse_buckets(enc: &str) -> Vec<&str> {
+        enc.lines()
+            .skip(2)
+            .filter_map(|s| {
+                let (_, s) = s.split_once("le=")?;
+                let s = s.split('\"').nth(1)?;
+                Some(s)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compose_metric_and_encode() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge_1"]
+                #[desc = "description"]
+                gauge_metric_1: Gauge,
+                #[name = "example_gauge_2"]
+                #[desc = "description"]
+                gauge_metric_2: Gauge,
+            }
+        }
+
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap();
+        metric.gauge_metric_1().inc();
+        metric.gauge_metric_2().inc();
+
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+
+        assert_eq!(
+            enc,
+            r#"# HELP example_gauge_1 description
+# TYPE example_gauge_1 gauge
+example_gauge_1 1
+# HELP example_gauge_2 description
+# TYPE example_gauge_2 gauge
+example_gauge_2 1
+"#
+        );
+    }
+
+    #[test]
+    fn with_name_desc() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap();
+        metric.gauge_metric().inc();
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+
+        assert_eq!(parse_name(&enc), "example_gauge");
+        assert_eq!(parse_description(&enc), "description");
+        assert_eq!(parse_type(&enc), "gauge");
+    }
+
+    #[test]
+    fn with_labels() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge_vec"]
+                #[desc = "description"]
+                #[labels = ["label1", "label2"]]
+                gauge_vec_metric: GaugeVec,
+            }
+        }
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap();
+        metric
+            .gauge_vec_metric()
+            .with_label_values(&["a", "b"])
+            .inc();
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+
+        assert_eq!(parse_name(&enc), "example_gauge_vec");
+        assert_eq!(parse_description(&enc), "description");
+        assert_eq!(parse_type(&enc), "gauge");
+        assert_eq!(parse_labels(&enc), vec!["label1", "label2"]);
+    }
+
+    #[test]
+    fn with_buckets() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hist"]
+                #[desc = "description"]
+                #[buckets = [0.1, 0.5]]
+                hist_metric: Histogram,
+            }
+        }
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap();
+        metric.hist_metric().observe(0.1);
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+
+        assert_eq!(parse_name(&enc), "example_hist");
+        assert_eq!(parse_description(&enc), "description");
+        assert_eq!(parse_type(&enc), "histogram");
+        assert_eq!(parse_buckets(&enc), vec!["0.1", "0.5", "+Inf"]);
+    }
+
+    #[test]
+    fn with_registry_field() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+                #[registry]
+                registry: Registry,
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter_metric: Counter,
+            }
+        }
+        let metric = CompositeMetric::new().unwrap();
+        metric.gauge_metric().inc();
+        metric.counter_metric().inc();
+        let enc = TextEncoder::new()
+            .encode_to_string(&metric.gather())
+            .unwrap();
+
+        assert_eq!(
+            enc,
+            r#"# HELP example_counter description
+# TYPE example_counter counter
+example_counter 1
+# HELP example_gauge description
+# TYPE example_gauge gauge
+example_gauge 1
+"#
+        );
+
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap();
+        metric.gauge_metric().inc();
+        assert_eq!(metric.gather().len(), 2);
+        assert_eq!(metric.registry().gather().len(), reg.gather().len());
+    }
+
+    #[test]
+    fn gauge_set_now() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+                #[name = "example_int_gauge"]
+                #[desc = "description"]
+                int_gauge_metric: prometheus::IntGauge,
+            }
+        }
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap();
+        metric.gauge_metric_set_now();
+        metric.int_gauge_metric_set_now();
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        assert!((now - metric.gauge_metric().get()).abs() < 5.0);
+        assert!((now - metric.int_gauge_metric().get() as f64).abs() < 5.0);
+    }
+
+    #[test]
+    fn reregister() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+                #[name = "example_counter_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                counter_vec_metric: CounterVec,
+            }
+        }
+        let reg_a = Registry::new();
+        let metric = CompositeMetric::register(&reg_a).unwrap();
+        metric.gauge_metric().set(2.0);
+        metric.counter_vec_metric().with_label_values(&["a"]).inc();
+
+        let reg_b = Registry::new();
+        metric.reregister(&reg_b).unwrap();
+        metric.gauge_metric().inc();
+
+        let enc_a = TextEncoder::new()
+            .encode_to_string(&reg_a.gather())
+            .unwrap();
+        let enc_b = TextEncoder::new()
+            .encode_to_string(&reg_b.gather())
+            .unwrap();
+        assert_eq!(enc_a, enc_b);
+        assert!(enc_b.contains("example_gauge 3"));
+        assert!(enc_b.contains("example_counter_vec{label=\"a\"} 1"));
+    }
+
+    #[test]
+    fn with_non_exhaustive() {
+        composite_metric! {
+            #[non_exhaustive]
+            #[derive(Clone)]
+            pub struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap().clone();
+        metric.gauge_metric().inc();
+
+        assert_eq!(metric.gauge_metric().get(), 1.0);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn opts_with_labels_alias() {
+        let vec: GaugeVec = crate::Opts::new("example_gauge_vec", "description")
+            .with_labels(&["label1", "label2"])
+            .try_into()
+            .unwrap();
+        let reg = Registry::new();
+        reg.register(Box::new(vec.clone())).unwrap();
+        vec.with_label_values(&["a", "b"]).inc();
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+
+        assert_eq!(parse_labels(&enc), vec!["label1", "label2"]);
+    }
+
+    #[test]
+    fn with_derive_value_eq() {
+        composite_metric! {
+            #[derive_value_eq]
+            struct CompositeMetric {
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter_metric: Counter,
+                #[name = "example_hist_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                #[buckets = [0.1, 0.5]]
+                hist_vec_metric: HistogramVec,
+            }
+        }
+        let metric_a = CompositeMetric::register(&Registry::new()).unwrap();
+        let metric_b = CompositeMetric::register(&Registry::new()).unwrap();
+        assert!(metric_a.values_eq(&metric_b));
+
+        for metric in [&metric_a, &metric_b] {
+            metric.counter_metric().inc();
+            metric
+                .hist_vec_metric()
+                .with_label_values(&["a"])
+                .observe(0.2);
+            metric
+                .hist_vec_metric()
+                .with_label_values(&["b"])
+                .observe(0.3);
+        }
+        assert!(metric_a.values_eq(&metric_b));
+
+        metric_b
+            .hist_vec_metric()
+            .with_label_values(&["b"])
+            .observe(0.05);
+        assert!(!metric_a.values_eq(&metric_b));
+    }
+
+    #[test]
+    fn with_buckets_ms() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hist"]
+                #[desc = "description"]
+                #[buckets_ms = [10, 50, 100, 500]]
+                hist_metric: Histogram,
+            }
+        }
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap();
+        metric.hist_metric().observe(0.1);
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+
+        assert_eq!(
+            parse_buckets(&enc),
+            vec!["0.01", "0.05", "0.1", "0.5", "+Inf"]
+        );
+    }
+
+    #[test]
+    fn with_single_label_and_trailing_comma() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge_vec_1"]
+                #[desc = "description"]
+                #[labels = ["only"]]
+                single_label: GaugeVec,
+                #[name = "example_gauge_vec_2"]
+                #[desc = "description"]
+                #[labels = ["only",]]
+                trailing_comma_label: GaugeVec,
+                #[name = "example_gauge_vec_3"]
+                #[desc = "description"]
+                #[labels = ["label1", "label2",]]
+                trailing_comma_labels: GaugeVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+
+        for vec in [metric.single_label(), metric.trailing_comma_label()] {
+            vec.with_label_values(&["a"]).inc();
+            let enc = TextEncoder::new().encode_to_string(&vec.collect()).unwrap();
+            assert_eq!(parse_labels(&enc), vec!["only"]);
+        }
+
+        let vec = metric.trailing_comma_labels();
+        vec.with_label_values(&["a", "b"]).inc();
+        let enc = TextEncoder::new().encode_to_string(&vec.collect()).unwrap();
+        assert_eq!(parse_labels(&enc), vec!["label1", "label2"]);
+    }
+
+    #[test]
+    fn with_lazy_register() {
+        composite_metric! {
+            #[lazy_register]
+            struct CompositeMetric {
+                #[name = "lazy_example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+        let gathered = || {
+            prometheus::gather()
+                .iter()
+                .any(|family| family.name() == "lazy_example_gauge")
+        };
+        let metric = CompositeMetric::new_lazy().unwrap();
+        assert!(!gathered());
+
+        metric.gauge_metric().inc();
+        assert!(gathered());
+        assert_eq!(metric.ensure_registered(), Ok(()));
+
+        let duplicate = CompositeMetric::new_lazy().unwrap();
+        duplicate.gauge_metric().inc();
+        assert!(duplicate.ensure_registered().is_err());
+        assert_eq!(duplicate.gauge_metric().get(), 1.0);
+    }
+
+    #[test]
+    fn lazy_register_after_explicit_registration() {
+        composite_metric! {
+            #[lazy_register]
+            struct CompositeMetric {
+                #[name = "lazy_explicit_example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+        let registry = Registry::new();
+        let metric = CompositeMetric::register(&registry).unwrap();
+        metric.gauge_metric().inc();
+
+        assert_eq!(metric.ensure_registered(), Ok(()));
+        assert!(!prometheus::gather()
+            .iter()
+            .any(|family| family.name() == "lazy_explicit_example_gauge"));
+        assert_eq!(registry.gather().len(), 1);
+    }
+
+    #[test]
+    fn register_with_context() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter"]
+                #[desc = "requests for {service} in {region}"]
+                counter_metric: Counter,
+            }
+        }
+        let reg = Registry::new();
+        let metric =
+            CompositeMetric::register_with_context(&reg, &[("service", "checkout")]).unwrap();
+        metric.counter_metric().inc();
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+
+        assert_eq!(
+            enc.lines().next(),
+            Some("# HELP example_counter requests for checkout in {region}")
+        );
+    }
+
+    #[test]
+    fn opts_into_prometheus_opts() {
+        let opts: PrometheusOpts = crate::Opts::new("example_counter", "description").into();
+        assert_eq!(opts.name, "example_counter");
+        assert_eq!(opts.help, "description");
+
+        let opts: HistogramOpts = crate::Opts::new("example_hist", "description")
+            .with_buckets(&[0.1, 0.5])
+            .into();
+        assert_eq!(opts.common_opts.name, "example_hist");
+        assert_eq!(opts.common_opts.help, "description");
+        assert_eq!(opts.buckets, vec![0.1, 0.5]);
+
+        let opts: HistogramOpts = crate::Opts::new("example_hist", "description").into();
+        assert_eq!(opts.buckets, DEFAULT_BUCKETS.to_vec());
+    }
+
+    #[test]
+    fn register_registry_like() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+
+        struct Wrapper(Registry);
+
+        impl RegistryLike for Wrapper {
+            fn as_registry(&self) -> &Registry {
+                &self.0
+            }
+        }
+
+        let wrapper = Wrapper(Registry::new());
+        let metric = CompositeMetric::register(&wrapper).unwrap();
+        metric.gauge_metric().inc();
+        assert_eq!(wrapper.0.gather().len(), 1);
+
+        let reg = std::sync::Arc::new(Registry::new());
+        CompositeMetric::register(&reg).unwrap();
+        assert_eq!(reg.gather().len(), 1);
+    }
+
+    #[test]
+    fn with_default_buckets() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hist_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                #[buckets = default]
+                hist_vec_metric: HistogramVec,
+                #[name = "example_hist"]
+                #[desc = "description"]
+                #[buckets = default]
+                hist_metric: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.hist_metric().observe(0.1);
+        let enc = TextEncoder::new()
+            .encode_to_string(&metric.hist_metric().collect())
+            .unwrap();
+        let expected = DEFAULT_BUCKETS
+            .iter()
+            .map(|b| b.to_string())
+            .chain(["+Inf".to_owned()])
+            .collect::<Vec<_>>();
+
+        assert_eq!(parse_buckets(&enc), expected);
+
+        metric
+            .hist_vec_metric()
+            .with_label_values(&["a"])
+            .observe(0.1);
+        let enc = TextEncoder::new()
+            .encode_to_string(&metric.hist_vec_metric().collect())
+            .unwrap();
+        assert_eq!(parse_buckets(&enc), expected);
+    }
+
+    #[test]
+    fn with_cfg_attr_buckets() {
+        composite_metric! {
+            struct CompositeMetric {
+                /// Conditionally bucketed histogram.
+                #[name = "example_hist"]
+                #[cfg_attr(test, desc = "test description")]
+                #[cfg_attr(not(test), desc = "description")]
+                #[cfg_attr(test, buckets = [0.5, 1.0])]
+                #[cfg_attr(not(test), buckets = [0.1, 0.2, 0.5, 1.0])]
+                hist_metric: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        let enc = TextEncoder::new()
+            .encode_to_string(&metric.hist_metric().collect())
+            .unwrap();
+        assert!(enc.contains("# HELP example_hist test description"));
+        assert_eq!(parse_buckets(&enc), ["0.5", "1", "+Inf"]);
+    }
+
+    #[test]
+    fn metric_name() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "app_http_requests"]
+                #[desc = "description"]
+                #[labels = ["path"]]
+                requests: IntCounterVec,
+                #[name = "app_uptime"]
+                #[desc = "description"]
+                uptime: Gauge,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.requests().with_label_values(&["/"]).inc();
+        metric.uptime().set(1.0);
+        assert_eq!(metric.requests_name(), "app_http_requests");
+        assert_eq!(metric.uptime_name(), "app_uptime");
+    }
+
+    #[test]
+    fn opts_clone() {
+        let base = crate::Opts::new("base", "description").with_buckets(&[0.5, 1.0]);
+        let first = crate::Opts {
+            name: "first".into(),
+            ..base.clone()
+        };
+        let second = crate::Opts {
+            name: "second".into(),
+            ..base.clone()
+        };
+
+        let first: Histogram = first.try_into().unwrap();
+        let second: Histogram = second.try_into().unwrap();
+        assert_eq!(first.desc()[0].fq_name, "first");
+        assert_eq!(second.desc()[0].fq_name, "second");
+        assert_eq!(base.name, "base");
+    }
+
+    #[test]
+    fn register_once() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter_metric: Counter,
+            }
+        }
+        let registry = std::sync::Arc::new(Registry::new());
+        let handles = (0..8)
+            .map(|_| {
+                let registry = registry.clone();
+                std::thread::spawn(move || {
+                    let metric = CompositeMetric::register_once(&registry).unwrap();
+                    metric.counter_metric().inc();
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let metric = CompositeMetric::register_once(&registry).unwrap();
+        assert_eq!(metric.counter_metric().get(), 8.0);
+        assert_eq!(registry.gather().len(), 1);
+    }
+
+    #[test]
+    fn register_once_lazy_register() {
+        composite_metric! {
+            #[lazy_register]
+            struct CompositeMetric {
+                #[name = "lazy_once_example_counter"]
+                #[desc = "description"]
+                counter_metric: Counter,
+            }
+        }
+        let registry = Registry::new();
+        CompositeMetric::register_once(&registry).unwrap();
+        let metric = CompositeMetric::register_once(&registry).unwrap();
+        metric.counter_metric().inc();
+
+        assert!(!prometheus::gather()
+            .iter()
+            .any(|family| family.name() == "lazy_once_example_counter"));
+        assert_eq!(registry.gather().len(), 1);
+    }
+
+    #[test]
+    fn buckets_strictly_increasing() {
+        assert!(__private::is_strictly_increasing(&[]));
+        assert!(__private::is_strictly_increasing(&[-1.0, 0.1, 0.5]));
+        assert!(!__private::is_strictly_increasing(&[0.5, 0.1]));
+        assert!(!__private::is_strictly_increasing(&[0.1, 0.1]));
+    }
+
+    #[test]
+    fn with_counter_health() {
+        composite_metric! {
+            #[counter_health]
+            struct CompositeMetric {
+                #[name = "example_advancing"]
+                #[desc = "description"]
+                advancing: Counter,
+                #[name = "example_stalled"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                stalled: CounterVec,
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge: Gauge,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.stalled().with_label_values(&["a"]).inc();
+        metric.gauge().set(1.0);
+
+        let before = metric.counter_snapshot();
+        metric.advancing().inc();
+        metric.gauge().inc();
+        let after = metric.counter_snapshot();
+
+        assert_eq!(before.advanced(&after), ["advancing"]);
+        assert_eq!(before.stalled(&after), ["stalled"]);
+        assert_eq!(after.get("stalled"), Some(1.0));
+        assert_eq!(after.get("gauge"), None);
+    }
+
+    #[test]
+    fn int_counter_inc_by_saturating() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_bytes"]
+                #[desc = "description"]
+                bytes: IntCounter,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.bytes().inc_by(u64::MAX - 10);
+        metric.bytes_inc_by_saturating(5);
+        assert_eq!(metric.bytes().get(), u64::MAX - 5);
+        metric.bytes_inc_by_saturating(100);
+        assert_eq!(metric.bytes().get(), u64::MAX);
+    }
+
+    #[test]
+    fn gauge_set_checked() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_ratio"]
+                #[desc = "description"]
+                ratio: Gauge,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.ratio_set_checked(0.5).unwrap();
+        assert!(metric.ratio_set_checked(f64::NAN).is_err());
+        assert!(metric.ratio_set_checked(f64::INFINITY).is_err());
+        assert_eq!(metric.ratio().get(), 0.5);
+    }
+
+    #[tokio::test]
+    async fn with_async_register() {
+        struct LockedRegistry(tokio::sync::Mutex<Registry>);
+
+        impl crate::AsyncRegistry for LockedRegistry {
+            async fn register(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+                self.0.lock().await.register(collector)
+            }
+        }
+
+        composite_metric! {
+            #[async_register]
+            struct CompositeMetric {
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter_metric: Counter,
+                #[name = "example_gauge_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                gauge_vec_metric: GaugeVec,
+            }
+        }
+        let registry = LockedRegistry(tokio::sync::Mutex::new(Registry::new()));
+        let metric = CompositeMetric::register_async(&registry).await.unwrap();
+        metric.counter_metric().inc();
+        metric.gauge_vec_metric().with_label_values(&["a"]).set(1.0);
+        assert_eq!(registry.0.lock().await.gather().len(), 2);
+        assert!(CompositeMetric::register_async(&registry).await.is_err());
+    }
+
+    #[test]
+    fn opts_debug() {
+        let opts = crate::Opts::new("example_name", "description").with_variable_labels(&["label"]);
+        let debug = format!("{opts:?}");
+        assert!(debug.contains("example_name"));
+        assert!(debug.contains("[\"label\"]"));
+    }
+
+    #[test]
+    fn try_from_borrowed_opts() {
+        let opts = crate::Opts::new("example_name", "description").with_variable_labels(&["label"]);
+        let counter = Counter::try_from(&opts).unwrap();
+        let gauge_vec = GaugeVec::try_from(&opts).unwrap();
+        assert_eq!(counter.desc()[0].fq_name, "example_name");
+        assert_eq!(gauge_vec.desc()[0].variable_labels, ["label"]);
+    }
+
+    #[test]
+    fn with_getter_prefix() {
+        composite_metric! {
+            #[getter_prefix = "get_"]
+            struct CompositeMetric {
+                #[registry]
+                registry: Registry,
+                /// Documented gauge.
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+        let metric = CompositeMetric::new().unwrap();
+        metric.get_gauge_metric().set(1.0);
+        assert_eq!(metric.gauge_metric_name(), "example_gauge");
+        assert_eq!(metric.get_registry().gather().len(), 1);
+    }
+
+    #[test]
+    fn collect_into() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[registry]
+                registry: Registry,
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter_metric: Counter,
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+        let metric = CompositeMetric::new().unwrap();
+        metric.counter_metric().inc();
+        metric.gauge_metric().set(1.0);
+
+        let mut families = Vec::new();
+        metric.collect_into(&mut families);
+        metric.collect_into(&mut families);
+        let names = families.iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "example_counter",
+                "example_gauge",
+                "example_counter",
+                "example_gauge"
+            ]
+        );
+        assert_eq!(metric.registry().gather().len(), 2);
+    }
+
+    #[test]
+    fn with_max_labels() {
+        composite_metric! {
+            #[max_labels = 2]
+            struct CompositeMetric {
+                #[name = "example_counter_vec"]
+                #[desc = "description"]
+                #[labels = ["method", "status"]]
+                counter_vec_metric: CounterVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric
+            .counter_vec_metric()
+            .with_label_values(&["GET", "200"])
+            .inc();
+    }
+
+    #[test]
+    fn metric_display() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter_metric: Counter,
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.counter_metric().inc_by(2.0);
+        metric.gauge_metric().set(1.0);
+        assert_eq!(
+            metric.counter_metric_display(),
+            "# HELP example_counter description\n\
+             # TYPE example_counter counter\n\
+             example_counter 2\n"
+        );
+    }
+
+    #[test]
+    fn register_conflicting_help() {
+        composite_metric! {
+            struct First {
+                #[name = "example_counter"]
+                #[desc = "first description"]
+                counter_metric: Counter,
+            }
+        }
+        composite_metric! {
+            struct Second {
+                #[name = "example_counter"]
+                #[desc = "second description"]
+                #[allow(dead_code)]
+                other_counter: Counter,
+            }
+        }
+        let registry = Registry::new();
+        let first = First::register(&registry).unwrap();
+        first.counter_metric().inc();
+        let err = Second::register(&registry).err().unwrap().to_string();
+        assert!(err.contains("`other_counter`"));
+        assert!(err.contains("\"first description\""));
+        assert!(err.contains("\"second description\""));
+    }
+
+    #[test]
+    fn with_raw_identifier() {
+        composite_metric! {
+            #[counter_health]
+            struct CompositeMetric {
+                #[name = "example_type"]
+                #[desc = "description"]
+                r#type: IntGauge,
+                #[name = "example_match"]
+                #[desc = "description"]
+                r#match: Counter,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.r#type().set(1);
+        metric.type_set_now();
+        metric.r#match().inc();
+        assert_eq!(metric.type_name(), "example_type");
+        assert_eq!(metric.counter_snapshot().get("match"), Some(1.0));
+    }
+
+    #[test]
+    fn reject_infinite_buckets() {
+        let opts =
+            crate::Opts::new("example_hist", "description").with_buckets(&[0.5, f64::INFINITY]);
+        let err = Histogram::try_from(&opts).err().unwrap();
+        assert!(err.to_string().contains("buckets must be finite"));
+
+        let opts = opts
+            .with_buckets(&[f64::NAN])
+            .with_variable_labels(&["label"]);
+        assert!(HistogramVec::try_from(opts).is_err());
+    }
+
+    #[test]
+    fn register_infinite_bucket_const() {
+        const BUCKETS: [f64; 2] = [0.5, f64::INFINITY];
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hist"]
+                #[desc = "description"]
+                #[buckets = BUCKETS]
+                #[allow(dead_code)]
+                hist: Histogram,
+            }
+        }
+
+        assert!(CompositeMetric::register(&Registry::new()).is_err());
+    }
+
+    #[test]
+    fn int_vec_get() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                counter_vec: IntCounterVec,
+                #[name = "example_gauge_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                gauge_vec: IntGaugeVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.counter_vec().with_label_values(&["a"]).inc_by(3);
+        metric.gauge_vec().with_label_values(&["a"]).set(-2);
+        assert_eq!(metric.counter_vec_get(&["a"]), 3);
+        assert_eq!(metric.counter_vec_get(&["b"]), 0);
+        assert_eq!(metric.gauge_vec_get(&["a"]), -2);
+        assert_eq!(metric.counter_vec().collect()[0].get_metric().len(), 1);
+    }
+
+    #[test]
+    fn histogram_vec_for() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hist_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                hist_vec: HistogramVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        let hist = metric.hist_vec_for(&["a"]);
+        for i in 0..100 {
+            hist.observe(f64::from(i) / 100.0);
+        }
+        let shared = metric.hist_vec().with_label_values(&["a"]);
+        assert_eq!(shared.get_sample_count(), 100);
+    }
+
+    #[test]
+    fn metric_catalog() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[registry]
+                registry: Registry,
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter: prometheus::IntCounter,
+                #[name = "example_gauge_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                gauge_vec: GaugeVec,
+                #[cfg_attr(test, name = "example_hist")]
+                #[cfg_attr(not(test), name = "example_release_hist")]
+                #[desc = "description"]
+                hist: Histogram,
+            }
+        }
+        assert_eq!(
+            CompositeMetric::metric_catalog(),
+            [
+                ("example_counter", "counter"),
+                ("example_gauge_vec", "gauge"),
+                ("example_hist", "histogram"),
+            ]
+        );
+        let metric = CompositeMetric::new().unwrap();
+        metric.counter().inc();
+        metric.gauge_vec().with_label_values(&["a"]).set(1.0);
+        metric.hist().observe(1.0);
+        assert_eq!(metric.registry().gather().len(), 3);
+    }
+
+    #[test]
+    fn opts_with_buckets_iter() {
+        let opts = crate::Opts::new("example_hist", "description")
+            .with_buckets_iter((1..=3).map(|ms| f64::from(ms) / 1000.0));
+        let hist: Histogram = opts.try_into().unwrap();
+        let enc = TextEncoder::new()
+            .encode_to_string(&hist.collect())
+            .unwrap();
+        assert_eq!(parse_buckets(&enc), ["0.001", "0.002", "0.003", "+Inf"]);
+    }
+
+    #[test]
+    fn try_register_names_metric() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter_metric: Counter,
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+            }
+        }
+        let registry = Registry::new();
+        registry
+            .register(Box::new(
+                Gauge::new("example_gauge", "description").unwrap(),
+            ))
+            .unwrap();
+        let err = CompositeMetric::try_register(&registry).err().unwrap();
+        assert_eq!(err.metric(), Some("gauge_metric"));
+        assert!(err
+            .to_string()
+            .starts_with("failed to register metric `gauge_metric`"));
+
+        let metric = CompositeMetric::try_register(&Registry::new()).unwrap();
+        metric.counter_metric().inc();
+        metric.gauge_metric().inc();
+    }
+
+    #[test]
+    fn with_labels_from() {
+        label_enum! {
+            #[label = "method"]
+            #[allow(dead_code)]
+            enum HttpMethod {
+                Get = "GET",
+                Post = "POST",
+                Put = "PUT",
+            }
+        }
+
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_requests"]
+                #[desc = "description"]
+                #[labels_from = HttpMethod]
+                requests: IntCounterVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.requests_with(HttpMethod::Post).inc();
+        assert_eq!(metric.requests_get(&["POST"]), 1);
+
+        let family = &metric.requests().collect()[0];
+        let mut values = family
+            .get_metric()
+            .iter()
+            .map(|m| m.get_label()[0].value())
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        assert_eq!(values, ["GET", "POST", "PUT"]);
+    }
+
+    #[test]
+    fn counter_vec_total() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                counter_vec: CounterVec,
+                #[name = "example_int_counter_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                int_counter_vec: IntCounterVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.counter_vec().with_label_values(&["a"]).inc_by(1.5);
+        metric.counter_vec().with_label_values(&["b"]).inc_by(2.0);
+        metric.int_counter_vec().with_label_values(&["a"]).inc();
+        assert_eq!(metric.counter_vec_total(), 3.5);
+        assert_eq!(metric.int_counter_vec_total(), 1.0);
+    }
+
+    #[test]
+    fn with_desc_from_file() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = include_str!("../tests/fixtures/gauge_desc.md")]
+                gauge_metric: Gauge,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.gauge_metric().set(1.0);
+        assert_eq!(
+            metric.gauge_metric().desc()[0].help,
+            "Number of active connections, including idle ones."
+        );
+    }
+
+    #[test]
+    fn histogram_const_labels() {
+        let opts = crate::Opts::new("example_hist", "description")
+            .with_const_labels(&[("service", "api")])
+            .with_buckets(&[0.5, 1.0]);
+        let hist = Histogram::try_from(&opts).unwrap();
+        hist.observe(0.7);
+        let enc = TextEncoder::new()
+            .encode_to_string(&hist.collect())
+            .unwrap();
+        assert!(enc.contains("example_hist_bucket{service=\"api\",le=\"0.5\"} 0"));
+        assert!(enc.contains("example_hist_count{service=\"api\"} 1"));
+
+        let counter = Counter::try_from(opts).unwrap();
+        assert_eq!(counter.desc()[0].const_label_pairs[0].value(), "api");
+    }
+
+    #[test]
+    fn label_arity_panic_location() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hist_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                hist_vec: HistogramVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+
+        // The hook is process-global, panics of tests on other threads are passed on.
+        static LOCATION: std::sync::Mutex<Option<(String, u32)>> = std::sync::Mutex::new(None);
+        let hook = std::sync::Arc::new(std::panic::take_hook());
+        let test_thread = std::thread::current().id();
+        let other_hook = hook.clone();
+        std::panic::set_hook(Box::new(move |info| {
+            if std::thread::current().id() != test_thread {
+                return other_hook(info);
+            }
+            let location = info.location().unwrap();
+            *LOCATION.lock().unwrap() = Some((location.file().to_owned(), location.line()));
+        }));
+        let line = line!() + 2;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            metric.hist_vec_for(&["a", "b"])
+        }));
+        std::panic::set_hook(Box::new(move |info| hook(info)));
+
+        assert!(result.is_err());
+        assert_eq!(metric.hist_vec().desc()[0].variable_labels, ["label"]);
+        assert_eq!(
+            LOCATION.lock().unwrap().take(),
+            Some((file!().to_owned(), line))
+        );
+    }
+
+    #[test]
+    fn gauge_guard() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge_metric: Gauge,
+                #[name = "example_int_gauge"]
+                #[desc = "description"]
+                int_gauge_metric: prometheus::IntGauge,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        let guard = metric.gauge_metric_guard();
+        let int_guards = [
+            metric.int_gauge_metric_guard(),
+            metric.int_gauge_metric_guard(),
+        ];
+        assert_eq!(metric.gauge_metric().get(), 1.0);
+        assert_eq!(metric.int_gauge_metric().get(), 2);
+
+        drop(guard);
+        drop(int_guards);
+        assert_eq!(metric.gauge_metric().get(), 0.0);
+        assert_eq!(metric.int_gauge_metric().get(), 0);
+    }
+
+    #[test]
+    fn register_with_template() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter: Counter,
+                #[name = "example_hist_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                hist_vec: HistogramVec,
+            }
+        }
+        let template = OptsTemplate::new()
+            .with_namespace("app")
+            .with_subsystem("api")
+            .with_const_labels(&[("tenant", "a")]);
+        let metric = CompositeMetric::register_with_template(&Registry::new(), &template).unwrap();
+
+        assert_eq!(metric.counter_name(), "app_api_example_counter");
+        assert_eq!(
+            metric.hist_vec().desc()[0].fq_name,
+            "app_api_example_hist_vec"
+        );
+        let desc = &metric.counter().desc()[0];
+        assert_eq!(desc.const_label_pairs[0].name(), "tenant");
+        assert_eq!(desc.const_label_pairs[0].value(), "a");
+    }
+
+    #[test]
+    fn enabled_gate() {
+        composite_metric! {
+            #[enabled_gate]
+            struct CompositeMetric {
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter: IntCounter,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        let other = CompositeMetric::register(&Registry::new()).unwrap();
+        assert!(metric.is_enabled());
+        if let Some(counter) = metric.counter_if_enabled() {
+            counter.inc();
+        }
+
+        metric.set_enabled(false);
+        assert!(metric.counter_if_enabled().is_none());
+        if let Some(counter) = metric.counter_if_enabled() {
+            counter.inc();
+        }
+        assert_eq!(metric.counter().get(), 1);
+
+        assert!(other.is_enabled());
+        if let Some(counter) = other.counter_if_enabled() {
+            counter.inc();
+        }
+        assert_eq!(other.counter().get(), 1);
+    }
+
+    #[test]
+    fn gather_filtered() {
+        composite_metric! {
+            struct FirstMetric {
+                #[name = "first_counter"]
+                #[desc = "description"]
+                counter: Counter,
+                #[name = "first_gauge"]
+                #[desc = "description"]
+                gauge: Gauge,
+            }
+        }
+        composite_metric! {
+            struct SecondMetric {
+                #[name = "second_counter"]
+                #[desc = "description"]
+                counter: Counter,
+            }
+        }
+        let reg = Registry::new();
+        let first = FirstMetric::register(&reg).unwrap();
+        let second = SecondMetric::register(&reg).unwrap();
+        assert_eq!(first.counter().get() + second.counter().get(), 0.0);
+        assert_eq!(first.gauge().get(), 0.0);
+
+        let names = |families: Vec<prometheus::proto::MetricFamily>| {
+            families
+                .iter()
+                .map(|f| f.name().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(reg.gather()).len(), 3);
+        assert_eq!(
+            names(first.gather_filtered(&reg)),
+            ["first_counter", "first_gauge"]
+        );
+        assert_eq!(names(second.gather_filtered(&reg)), ["second_counter"]);
+    }
+
+    #[test]
+    fn slo_buckets() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_latency"]
+                #[desc = "description"]
+                #[slo_buckets = [0.3, 1.0]]
+                latency: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        assert_eq!(metric.latency_slo_fractions(), [(0.3, 0.0), (1.0, 0.0)]);
+        for v in [0.1, 0.2, 0.25, 0.5, 2.0] {
+            metric.latency().observe(v);
+        }
+
+        let fractions = metric.latency_slo_fractions();
+        assert_eq!(fractions[0], (0.3, 0.6));
+        assert_eq!(fractions[1], (1.0, 0.8));
+    }
+
+    #[test]
+    fn into_iterator() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[registry]
+                registry: Registry,
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter: Counter,
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge: Gauge,
+            }
+        }
+        let metric = CompositeMetric::new().unwrap();
+        metric.counter().inc();
+        assert_eq!(metric.gauge().get(), 0.0);
+
+        assert_eq!((&metric).into_iter().count(), 2);
+        let reg = Registry::new();
+        for collector in &metric {
+            reg.register(collector).unwrap();
+        }
+        assert_eq!(reg.gather().len(), 2);
+        assert_eq!(metric.registry().gather().len(), 2);
+    }
+
+    #[test]
+    fn prefer_int() {
+        composite_metric! {
+            #[prefer_int]
+            struct CompositeMetric {
+                #[name = "example_gauge"]
+                #[desc = "description"]
+                gauge: Gauge,
+                #[name = "example_counter"]
+                #[desc = "description"]
+                counter: Counter,
+                #[name = "example_hist"]
+                #[desc = "description"]
+                hist: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        let gauge: &IntGauge = metric.gauge();
+        let counter: &IntCounter = metric.counter();
+        gauge.set(2);
+        counter.inc_by(3);
+        metric.hist().observe(1.0);
+        assert_eq!(metric.gauge().get() + metric.counter().get() as i64, 5);
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hist"]
+                #[desc = "description"]
+                #[buckets = [0.1, 0.5, 1.0]]
+                hist: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        for v in [0.05, 0.2, 0.3, 2.0] {
+            metric.hist().observe(v);
+        }
+
+        assert_eq!(
+            metric.hist_buckets(),
+            [(0.1, 1), (0.5, 3), (1.0, 3), (f64::INFINITY, 4)]
+        );
+    }
+
+    #[test]
+    fn labels_struct() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter_vec"]
+                #[desc = "description"]
+                #[labels = ["method", "status"]]
+                counter_vec: CounterVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric
+            .counter_vec_with_labels(CompositeMetricCounterVecLabels {
+                status: "500",
+                method: "POST",
+            })
+            .inc();
+
+        assert_eq!(
+            metric
+                .counter_vec()
+                .with_label_values(&["POST", "500"])
+                .get(),
+            1.0
+        );
+        assert_eq!(metric.counter_vec_total(), 1.0);
+    }
+
+    #[test]
+    fn labels_struct_names() {
+        composite_metric! {
+            struct FirstMetric {
+                #[name = "first_requests"]
+                #[desc = "description"]
+                #[labels = ["method"]]
+                requests: IntCounterVec,
+            }
+        }
+        composite_metric! {
+            struct SecondMetric {
+                #[name = "second_requests"]
+                #[desc = "description"]
+                #[labels = ["method"]]
+                requests: IntCounterVec,
+                #[name = "second_events"]
+                #[desc = "description"]
+                #[labels = ["type"]]
+                events: IntCounterVec,
+            }
+        }
+
+        let registry = Registry::new();
+        let first = FirstMetric::register(&registry).unwrap();
+        let second = SecondMetric::register(&registry).unwrap();
+        first
+            .requests_with_labels(FirstMetricRequestsLabels { method: "GET" })
+            .inc();
+        second
+            .requests_with_labels(SecondMetricRequestsLabels { method: "GET" })
+            .inc();
+        second.events().with_label_values(&["start"]).inc();
+        assert_eq!(first.requests().with_label_values(&["GET"]).get(), 1);
+        assert_eq!(second.requests().with_label_values(&["GET"]).get(), 1);
+        assert_eq!(second.events_get(&["start"]), 1);
+    }
+
+    #[test]
+    fn combine() {
+        composite_metric! {
+            #[combine]
+            struct CoreMetric {
+                #[name = "core_counter"]
+                #[desc = "description"]
+                counter: Counter,
+            }
+        }
+        composite_metric! {
+            struct OptionalMetric {
+                #[name = "optional_gauge"]
+                #[desc = "description"]
+                gauge: Gauge,
+                #[name = "optional_counter"]
+                #[desc = "description"]
+                counter: IntCounter,
+            }
+        }
+        let reg = Registry::new();
+        let core = CoreMetric::register(&reg).unwrap();
+        let metric = core.merge(OptionalMetric::register(&reg).unwrap());
+        metric.first().counter().inc();
+        metric.second().gauge().set(2.0);
+        metric.second().counter().inc();
+
+        assert_eq!(metric.into_iter().count(), 3);
+        assert_eq!(reg.gather().len(), 3);
+        let (core, optional) = metric.into_parts();
+        assert_eq!(core.counter().get() + optional.gauge().get(), 3.0);
+        assert_eq!(optional.counter().get(), 1);
+    }
+
+    #[test]
+    fn buckets_shorthand() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_exp_hist"]
+                #[desc = "description"]
+                #[buckets = exp(0.001, 2.0, 4)]
+                exp_hist: Histogram,
+                #[name = "example_linear_hist"]
+                #[desc = "description"]
+                #[buckets = linear(0.5, 0.25, 3)]
+                linear_hist: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.exp_hist().observe(0.003);
+        metric.linear_hist().observe(0.6);
+
+        let bounds =
+            |buckets: Vec<(f64, u64)>| buckets.into_iter().map(|(b, _)| b).collect::<Vec<_>>();
+        assert_eq!(
+            bounds(metric.exp_hist_buckets()),
+            [0.001, 0.002, 0.004, 0.008, f64::INFINITY]
+        );
+        assert_eq!(
+            bounds(metric.linear_hist_buckets()),
+            [0.5, 0.75, 1.0, f64::INFINITY]
+        );
+    }
+
+    #[test]
+    fn buckets_shorthand_invalid() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_exp_hist"]
+                #[desc = "description"]
+                #[buckets = exp(0.001, 2.0, 0)]
+                exp_hist: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new());
+        assert!(metric.map(|metric| metric.exp_hist().clone()).is_err());
+    }
+
+    #[test]
+    fn opts_overlapping_labels() {
+        let opts = crate::Opts::new("example_counter_vec", "description")
+            .with_variable_labels(&["service", "method"])
+            .with_const_labels(&[("service", "api")]);
+        let err = CounterVec::try_from(&opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error: label service of example_counter_vec is both a const and a variable label"
+        );
+        assert!(HistogramVec::try_from(&opts).is_err());
+        assert!(CounterVec::try_from(opts.with_variable_labels(&["method"])).is_ok());
+    }
+
+    #[test]
+    fn as_text() {
+        composite_metric! {
+            #[lazy_register]
+            struct CompositeMetric {
+                #[name = "example_unregistered_counter"]
+                #[desc = "description"]
+                counter: Counter,
+            }
+        }
+        let metric = CompositeMetric::new_lazy().unwrap();
+
+        assert_eq!(
+            metric.as_text().unwrap(),
+            "# HELP example_unregistered_counter description\n\
+             # TYPE example_unregistered_counter counter\n\
+             example_unregistered_counter 0\n"
+        );
+        assert!(prometheus::gather()
+            .iter()
+            .all(|f| f.name() != "example_unregistered_counter"));
+        metric.counter().inc();
+
+        composite_metric! {
+            #[lazy_register]
+            struct Duplicate {
+                #[name = "example_duplicate_counter"]
+                #[desc = "description"]
+                #[allow(dead_code)]
+                first: Counter,
+                #[name = "example_duplicate_counter"]
+                #[desc = "description"]
+                #[allow(dead_code)]
+                second: Counter,
+            }
+        }
+        assert!(Duplicate::new_lazy().unwrap().as_text().is_err());
+    }
+
+    #[test]
+    fn label_patterns() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_counter_vec"]
+                #[desc = "description"]
+                #[labels = ["method", "status"]]
+                #[label_patterns = ["^(GET|POST|PUT)$", "[1-5]..|.*"]]
+                counter_vec: IntCounterVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.counter_vec_checked(&["POST", "200"]).unwrap().inc();
+
+        let err = metric.counter_vec_checked(&["TRACE", "200"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error: label value \"TRACE\" of counter_vec doesn't match \"^(GET|POST|PUT)$\""
+        );
+        assert!(metric.counter_vec_checked(&["GET"]).is_err());
+        assert_eq!(metric.counter_vec().collect()[0].get_metric().len(), 1);
+    }
+
+    #[test]
+    fn custom_atomic_gauge() {
+        use prometheus::core::{Atomic, GenericGauge};
+
+        #[derive(Debug)]
+        struct LockedF64(std::sync::Mutex<f64>);
+
+        impl Atomic for LockedF64 {
+            type T = f64;
+
+            fn new(val: f64) -> Self {
+                Self(std::sync::Mutex::new(val))
+            }
+
+            fn set(&self, val: f64) {
+                *self.0.lock().unwrap() = val;
+            }
+
+            fn get(&self) -> f64 {
+                *self.0.lock().unwrap()
+            }
+
+            fn inc_by(&self, delta: f64) {
+                *self.0.lock().unwrap() += delta;
+            }
+
+            fn dec_by(&self, delta: f64) {
+                *self.0.lock().unwrap() -= delta;
+            }
+        }
+
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_locked_gauge"]
+                #[desc = "description"]
+                gauge: GenericGauge<LockedF64>,
+            }
+        }
+        let reg = Registry::new();
+        let metric = CompositeMetric::register(&reg).unwrap();
+        metric.gauge().set(1.5);
+        let guard = metric.gauge_guard();
+        assert_eq!(metric.gauge().get(), 2.5);
+        drop(guard);
+        metric.gauge_set_now();
+        assert!(metric.gauge().get() > 1_000_000_000.0);
+        assert_eq!(reg.gather()[0].get_metric().len(), 1);
+    }
+
+    #[test]
+    fn into_iterator_declaration_order() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_zeta"]
+                #[desc = "description"]
+                zeta: Counter,
+                #[name = "example_alpha"]
+                #[desc = "description"]
+                alpha: Gauge,
+                #[name = "example_mu"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                mu: IntCounterVec,
+                #[name = "example_beta"]
+                #[desc = "description"]
+                beta: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        assert_eq!(metric.zeta().get() + metric.alpha().get(), 0.0);
+        assert_eq!(metric.mu().collect()[0].get_metric().len(), 0);
+        assert_eq!(metric.beta().get_sample_count(), 0);
+
+        let names = || {
+            (&metric)
+                .into_iter()
+                .map(|collector| collector.desc()[0].fq_name.clone())
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            "example_zeta",
+            "example_alpha",
+            "example_mu",
+            "example_beta",
+        ];
+        for _ in 0..3 {
+            assert_eq!(names(), expected);
+        }
+    }
+
+    #[test]
+    fn histogram_is_observed() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hist"]
+                #[desc = "description"]
+                hist: Histogram,
+                #[name = "example_hist_vec"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                hist_vec: HistogramVec,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        assert!(!metric.hist_is_observed());
+        assert!(!metric.hist_vec_is_observed());
+        metric.hist_vec().with_label_values(&["a"]);
+        assert!(!metric.hist_vec_is_observed());
+
+        metric.hist().observe(0.1);
+        metric.hist_vec_for(&["b"]).observe(0.1);
+        assert!(metric.hist_is_observed());
+        assert!(metric.hist_vec_is_observed());
+    }
+
+    #[test]
+    fn opts_owned() {
+        let names = ["first", "second"].map(|plugin| {
+            let opts = crate::Opts::owned(
+                format!("plugin_{plugin}_calls"),
+                format!("Calls of the {plugin} plugin"),
+            );
+            let counter = IntCounter::try_from(opts).unwrap();
+            counter.desc()[0].fq_name.clone()
+        });
+        assert_eq!(names, ["plugin_first_calls", "plugin_second_calls"]);
+
+        let buckets = crate::Opts::owned("example_hist".to_owned(), "description".to_owned())
+            .with_buckets_iter([0.5, 1.0]);
+        let hist = Histogram::try_from(buckets).unwrap();
+        hist.observe(0.7);
+        assert_eq!(hist.get_sample_count(), 1);
+    }
+
+    #[test]
+    fn default_desc() {
+        composite_metric! {
+            #[default_desc = "Queue size of the {queue} workers"]
+            struct CompositeMetric {
+                #[name = "example_queue_size"]
+                queue_size: IntGauge,
+                #[name = "example_queue_capacity"]
+                queue_capacity: IntGauge,
+                #[name = "example_queue_drops"]
+                #[desc = "Dropped jobs"]
+                queue_drops: IntCounter,
+            }
+        }
+        let metric =
+            CompositeMetric::register_with_context(&Registry::new(), &[("queue", "io")]).unwrap();
+        metric.queue_drops().inc();
+
+        assert_eq!(
+            metric.queue_size().desc()[0].help,
+            "Queue size of the io workers"
+        );
+        assert_eq!(
+            metric.queue_capacity().desc()[0].help,
+            "Queue size of the io workers"
+        );
+        assert_eq!(metric.queue_drops().desc()[0].help, "Dropped jobs");
+    }
+
+    #[test]
+    fn histogram_observe_checked() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_latency"]
+                #[desc = "Example latency"]
+                #[buckets = [0.1, 1.0]]
+                latency: Histogram,
+                #[name = "example_offset"]
+                #[desc = "Example clock offset"]
+                #[buckets = [-1.0, 0.0, 1.0]]
+                offset: Histogram,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+
+        metric.latency_observe_checked(0.5).unwrap();
+        let err = metric.latency_observe_checked(-0.5).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error: invalid observation -0.5 for histogram latency"
+        );
+        assert!(metric.latency_observe_checked(f64::NAN).is_err());
+        assert_eq!(metric.latency().get_sample_count(), 1);
+
+        metric.offset_observe_checked(-0.5).unwrap();
+        assert!(metric.offset_observe_checked(f64::INFINITY).is_err());
+        assert_eq!(metric.offset().get_sample_count(), 1);
+    }
+
+    #[test]
+    fn register_with_runtime_buckets() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_latency"]
+                #[desc = "Example latency"]
+                #[buckets = [0.1, 1.0]]
+                latency: Histogram,
+                #[name = "example_size"]
+                #[desc = "Example size"]
+                #[labels = ["kind"]]
+                #[buckets = [10.0, 100.0]]
+                size: HistogramVec,
+                #[name = "example_requests"]
+                #[desc = "Example requests"]
+                requests: IntCounter,
+            }
+        }
+        let metric = CompositeMetric::register_with(&Registry::new(), |field| {
+            assert_ne!(field, "requests");
+            (field == "latency").then(|| vec![0.5, 2.0, 5.0])
+        })
+        .unwrap();
+        metric.requests().inc();
+        metric.latency().observe(1.0);
+        metric.size().with_label_values(&["a"]).observe(50.0);
+
+        let bounds =
+            |buckets: Vec<(f64, u64)>| buckets.into_iter().map(|(b, _)| b).collect::<Vec<_>>();
+        assert_eq!(
+            bounds(metric.latency_buckets()),
+            [0.5, 2.0, 5.0, f64::INFINITY]
+        );
+        let size = &metric.size().collect()[0];
+        assert_eq!(
+            bounds(crate::__private::cumulative_buckets(size)),
+            [10.0, 100.0, f64::INFINITY]
+        );
+    }
+
+    #[test]
+    fn register_with_invalid_runtime_buckets() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_latency"]
+                #[desc = "Example latency"]
+                #[allow(dead_code)]
+                latency: Histogram,
+            }
+        }
+        let result =
+            CompositeMetric::register_with(&Registry::new(), |_| Some(vec![f64::INFINITY]));
+        let Err(err) = result else {
+            panic!("registered invalid buckets");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Error: invalid bucket inf for example_latency, buckets must be finite"
+        );
+    }
+
+    #[test]
+    fn struct_collector() {
+        composite_metric! {
+            #[collector]
+            #[derive(Clone)]
+            struct CompositeMetric {
+                #[name = "example_requests"]
+                #[desc = "Example requests"]
+                requests: IntCounter,
+                #[name = "example_in_flight"]
+                #[desc = "Example in-flight requests"]
+                in_flight: IntGauge,
+            }
+        }
+        let registry = Registry::new();
+        let metric = CompositeMetric::register(&registry).unwrap();
+        metric.requests().inc();
+        metric.in_flight().set(2);
+
+        let whole = Registry::new();
+        whole.register(Box::new(metric.clone())).unwrap();
+        let names = |registry: &Registry| {
+            registry
+                .gather()
+                .iter()
+                .map(|family| family.name().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&registry), names(&whole));
+        assert_eq!(whole.gather()[1].get_metric()[0].get_counter().value(), 1.0);
+
+        let err = registry.register(Box::new(metric)).unwrap_err();
+        assert!(matches!(err, prometheus::Error::AlreadyReg));
+    }
+
+    #[test]
+    fn const_generic_names() {
+        composite_metric! {
+            struct ShardMetrics<const N: usize> {
+                #[name = const_name!("shard_", N, "_requests")]
+                #[desc = "Requests of the shard"]
+                requests: IntCounter,
+            }
+        }
+        let registry = Registry::new();
+        let first = ShardMetrics::<0>::register(&registry).unwrap();
+        let second = ShardMetrics::<12>::register(&registry).unwrap();
+        first.requests().inc();
+        second.requests().inc_by(2);
+
+        assert_eq!(first.requests_name(), "shard_0_requests");
+        assert_eq!(second.requests_name(), "shard_12_requests");
+        assert_eq!(
+            ShardMetrics::<0>::metric_catalog(),
+            [("shard_0_requests", "counter")]
+        );
+        assert_eq!(
+            ShardMetrics::<12>::metric_catalog(),
+            [("shard_12_requests", "counter")]
+        );
+        assert_eq!(registry.gather().len(), 2);
+    }
+
+    #[test]
+    fn content_types() {
+        assert_eq!(
+            TEXT_CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+        assert!(TEXT_CONTENT_TYPE.starts_with(prometheus::TEXT_FORMAT));
+        assert_eq!(
+            OPENMETRICS_CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn pair_with_counter() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_request_latency"]
+                #[desc = "Example request latency"]
+                #[labels = ["path"]]
+                #[pair_with = requests]
+                request_latency: HistogramVec,
+                #[name = "example_requests"]
+                #[desc = "Example requests"]
+                #[labels = ["path"]]
+                requests: IntCounterVec,
+                #[name = "example_job_duration"]
+                #[desc = "Example job duration"]
+                #[pair_with = jobs]
+                job_duration: Histogram,
+                #[name = "example_jobs"]
+                #[desc = "Example jobs"]
+                jobs: Counter,
+            }
         }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.request_latency_record(&["/"], 0.2);
+        metric.request_latency_record(&["/"], 0.4);
+        metric.job_duration_record(&[], 3.0);
 
-        impl $name {
-            $v fn register(registry: &::prometheus::Registry) -> ::prometheus::Result<Self> {
-                $(
-                    let opts = $crate::Opts::new($prom_name, $prom_desc);
-                    $(
-                        let opts = opts
-                            .with_labels(&$prom_labels);
-                    )?
-                    $(
-                        let opts = opts
-                            .with_buckets(&$prom_buckets);
-                    )?
-                    let $metric_name: $metric_ty = opts.try_into().unwrap();
-                    registry.register(::std::boxed::Box::new($metric_name.clone()))?;
-                )+
+        let latency = metric.request_latency().with_label_values(&["/"]);
+        assert_eq!(latency.get_sample_count(), 2);
+        assert_eq!(metric.requests().with_label_values(&["/"]).get(), 2);
+        assert_eq!(metric.job_duration().get_sample_sum(), 3.0);
+        assert_eq!(metric.jobs().get(), 1.0);
+    }
 
-                Ok(Self {
-                    $(
-                        $metric_name
-                    ),+
-                })
+    #[test]
+    fn register_with_name_fn() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example.requests.total"]
+                #[desc = "Example requests"]
+                requests: IntCounter,
+                #[name = "example.latency"]
+                #[desc = "Example latency"]
+                latency: Histogram,
             }
+        }
+        let metric =
+            CompositeMetric::register_with_name_fn(&Registry::new(), |name| name.replace('.', "_"))
+                .unwrap();
+        metric.requests().inc();
+        metric.latency().observe(0.1);
 
+        assert_eq!(metric.requests_name(), "example_requests_total");
+        assert_eq!(metric.latency_name(), "example_latency");
+    }
 
-            $(
-                $v fn $metric_name (&self) -> &$metric_ty {
-                    &self.$metric_name
-                }
-            )+
-        }
-    };
-}
+    #[test]
+    fn from_prometheus_opts() {
+        let prom_opts = PrometheusOpts::new("requests_total", "Requests")
+            .namespace("app")
+            .subsystem("http");
+        let opts = crate::Opts::from(&prom_opts);
+        let counter = IntCounter::try_from(&opts).unwrap();
+        assert_eq!(counter.desc()[0].fq_name, "app_http_requests_total");
+        assert_eq!(counter.desc()[0].help, "Requests");
 
-/// A more generic prometheus options that allow construction of both scalar and vector metrics.
-#[derive(Default)]
-pub struct Opts<'a> {
-    name: &'a str,
-    desc: &'a str,
-    labels: Option<&'a [&'a str]>,
-    buckets: Option<&'a [f64]>,
-}
+        let round_trip = PrometheusOpts::from(opts);
+        assert_eq!(round_trip.fq_name(), prom_opts.fq_name());
+        assert_eq!(round_trip.help, prom_opts.help);
 
-impl<'a> Opts<'a> {
-    /// Create a new generic metric option based name, helper text and optional labels.
-    pub fn new(name: &'a str, desc: &'a str) -> Self {
-        Self {
-            name,
-            desc,
-            ..Self::default()
-        }
-    }
+        let labeled = PrometheusOpts::new("labeled", "Labeled").const_label("service", "api");
+        let opts = crate::Opts::from(&labeled);
+        let counter = IntCounter::try_from(&opts).unwrap();
+        assert_eq!(counter.desc()[0].const_label_pairs[0].value(), "api");
+        assert_eq!(
+            PrometheusOpts::from(opts).const_labels,
+            labeled.const_labels
+        );
 
-    /// Attaches labels to the options.
-    pub fn with_labels(mut self, labels: &'a [&'a str]) -> Self {
-        self.labels = labels.into();
-        self
+        let plain = PrometheusOpts::new("plain", "Plain");
+        assert_eq!(
+            PrometheusOpts::from(crate::Opts::from(&plain)).fq_name(),
+            "plain"
+        );
     }
 
-    /// Attaches buckets to the options.
-    pub fn with_buckets(mut self, buckets: &'a [f64]) -> Self {
-        self.buckets = buckets.into();
-        self
+    #[test]
+    fn metric_by_id() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[registry]
+                registry: Registry,
+                #[name = "example_request_count"]
+                #[desc = "Example requests"]
+                request_count: IntCounter,
+                #[name = "example_in_flight"]
+                #[desc = "Example in-flight requests"]
+                in_flight: IntGauge,
+            }
+        }
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.request_count().inc();
+        metric.in_flight().set(4);
+        assert_eq!(metric.registry().gather().len(), 2);
+
+        let requests = metric
+            .metric(CompositeMetricMetricId::RequestCount)
+            .collect();
+        assert_eq!(requests[0].name(), "example_request_count");
+        assert_eq!(requests[0].get_metric()[0].get_counter().value(), 1.0);
+        let in_flight = metric.metric(CompositeMetricMetricId::InFlight).desc();
+        assert_eq!(in_flight[0].fq_name, "example_in_flight");
     }
-}
 
-macro_rules! impl_try_from {
-    ($ident:ident, $opts:ident $(,)? $($param:ident),*) => {
-        impl TryFrom<Opts<'_>> for $ident {
-            type Error = prometheus::Error;
-            fn try_from(opts: Opts<'_>) -> Result<Self, Self::Error> {
-                #[allow(unused_mut)]
-                let mut prom_opts = <$opts>::new(opts.name, opts.desc);
-                $(
-                    if let Some(param) = opts.$param {
-                        prom_opts.$param = param.into();
-                    }
-                )*
-                <$ident>::with_opts(prom_opts.into())
+    #[test]
+    fn default_buckets() {
+        composite_metric! {
+            #[default_buckets = [0.01, 0.1, 1.0]]
+            struct CompositeMetric {
+                #[name = "example_read_latency"]
+                #[desc = "Example read latency"]
+                read_latency: Histogram,
+                #[name = "example_write_latency"]
+                #[desc = "Example write latency"]
+                #[labels = ["table"]]
+                #[buckets = [0.5, 5.0]]
+                write_latency: HistogramVec,
+                #[name = "example_writes"]
+                #[desc = "Example writes"]
+                writes: IntCounter,
             }
         }
-    };
-}
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.read_latency().observe(0.05);
+        metric
+            .write_latency()
+            .with_label_values(&["a"])
+            .observe(1.0);
+        metric.writes().inc();
 
-impl_try_from!(Counter, PrometheusOpts);
-impl_try_from!(IntGauge, PrometheusOpts);
-impl_try_from!(Gauge, PrometheusOpts);
-impl_try_from!(Histogram, HistogramOpts, buckets);
+        let bounds = |family: &prometheus::proto::MetricFamily| {
+            crate::__private::cumulative_buckets(family)
+                .into_iter()
+                .map(|(bound, _)| bound)
+                .collect::<Vec<_>>()
+        };
+        let read = &metric.read_latency().collect()[0];
+        assert_eq!(bounds(read), [0.01, 0.1, 1.0, f64::INFINITY]);
+        let write = &metric.write_latency().collect()[0];
+        assert_eq!(bounds(write), [0.5, 5.0, f64::INFINITY]);
+    }
 
-macro_rules! impl_try_from_vec {
-    ($ident:ident, $opts:ident $(,)? $($param:ident),*) => {
-        impl TryFrom<Opts<'_>> for $ident {
-            type Error = prometheus::Error;
-            fn try_from(opts: Opts<'_>) -> Result<Self, Self::Error> {
-                #[allow(unused_mut)]
-                let mut prom_opts = <$opts>::new(opts.name, opts.desc);
-                $(
-                    if let Some(param) = opts.$param {
-                        prom_opts.$param = param.into();
-                    }
-                )*
-                <$ident>::new(
-                    prom_opts.into(),
-                    opts.labels.ok_or_else(|| {
-                        prometheus::Error::Msg("vec requires one or more labels".to_owned())
-                    })?,
-                )
+    #[test]
+    fn inc_sampled() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "example_hot_calls"]
+                #[desc = "Example hot calls"]
+                hot_calls: IntCounter,
+                #[name = "example_hot_bytes"]
+                #[desc = "Example hot bytes"]
+                hot_bytes: Counter,
             }
         }
-    };
-}
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        for _ in 0..100_000 {
+            metric.hot_calls_inc_sampled(10);
+            metric.hot_bytes_inc_sampled(1);
+        }
 
-impl_try_from_vec!(IntCounterVec, PrometheusOpts);
-impl_try_from_vec!(CounterVec, PrometheusOpts);
-impl_try_from_vec!(GaugeVec, PrometheusOpts);
-impl_try_from_vec!(IntGaugeVec, PrometheusOpts);
-impl_try_from_vec!(HistogramVec, HistogramOpts, buckets);
+        let total = metric.hot_calls().get();
+        assert!(total.is_multiple_of(10));
+        assert!((95_000..=105_000).contains(&total), "sampled total {total}");
+        assert_eq!(metric.hot_bytes().get(), 100_000.0);
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::*;
-    use prometheus::*;
+    #[test]
+    fn register_to_mock_registrar() {
+        #[derive(Default)]
+        struct MockRegistrar(std::sync::Mutex<Vec<Box<dyn Collector>>>);
 
-    fn parse_name(enc: &str) -> &str {
-        enc.lines()
-            .next()
-            .expect("mutliple lines")
-            .split(' ')
-            .nth(2)
-            .expect("description line")
-    }
+        impl crate::Registrar for MockRegistrar {
+            fn register_collector(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+                self.0.lock().unwrap().push(collector);
+                Ok(())
+            }
+        }
 
-    fn parse_description(enc: &str) -> &str {
-        enc.lines()
-            .next()
-            .expect("mutliple lines")
-            .split(' ')
-            .nth(3)
-            .expect("description line")
-    }
+        composite_metric! {
+            struct CompositeMetric {
+                #[registry]
+                registry: Registry,
+                #[name = "example_requests"]
+                #[desc = "Example requests"]
+                requests: IntCounter,
+                #[name = "example_latency"]
+                #[desc = "Example latency"]
+                latency: Histogram,
+            }
+        }
+        let registrar = MockRegistrar::default();
+        let metric = CompositeMetric::register(&registrar).unwrap();
+        metric.requests().inc();
+        metric.latency().observe(0.1);
 
-    fn parse_type(enc: &str) -> &str {
-        enc.lines()
-            .nth(1)
-            .expect("mutliple lines")
-            .split(' ')
-            .nth(3)
-            .expect("type line")
+        let captured = registrar.0.lock().unwrap();
+        let names = captured
+            .iter()
+            .map(|collector| collector.desc()[0].fq_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["example_requests", "example_latency"]);
+        assert_eq!(
+            captured[0].collect()[0].get_metric()[0]
+                .get_counter()
+                .value(),
+            1.0
+        );
+        assert!(metric.registry().gather().is_empty());
     }
 
-    fn parse_labels(enc: &str) -> Vec<&str> {
-        let (_, s) = enc
-            .lines()
-            .nth(2)
-            .expect("mutliple lines")
-            .split_once('{')
+    #[test]
+    fn opts_with_owned_labels() {
+        let labels = ["service", "method"].into_iter().collect::<Vec<_>>();
+        let vec: IntCounterVec = crate::Opts::new("example_counter_vec", "description")
+            .with_variable_labels(labels)
+            .try_into()
             .unwrap();
-        let (s, _) = s.split_once('}').unwrap();
-        s.split(',')
-            .filter_map(|s| {
-                let (l, _) = s.split_once('=')?;
-                Some(l)
-            })
-            .collect()
+        vec.with_label_values(&["api", "GET"]).inc();
+        assert_eq!(vec.with_label_values(&["api", "GET"]).get(), 1);
+
+        let labels: &[&str] = &["label"];
+        let opts =
+            crate::Opts::new("example_gauge_vec", "description").with_variable_labels(labels);
+        assert!(GaugeVec::try_from(opts).is_ok());
     }
 
-    fn parse_buckets(enc: &str) -> Vec<&str> {
-        enc.lines()
-            .skip(2)
-            .filter_map(|s| {
-                let (_, s) = s.split_once("le=")?;
-                let s = s.split('\"').nth(1)?;
-                Some(s)
-            })
-            .collect()
+    #[test]
+    fn reject_empty_buckets() {
+        const NO_BUCKETS: [f64; 0] = [];
+        let opts = crate::Opts::new("example_hist", "description").with_buckets(&NO_BUCKETS);
+        let err = Histogram::try_from(&opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error: empty buckets for example_hist, at least one bucket is required"
+        );
+        let opts = opts.with_variable_labels(&["label"]);
+        assert!(HistogramVec::try_from(opts).is_err());
     }
 
     #[test]
-    fn compose_metric_and_encode() {
+    fn register_empty_bucket_const() {
+        const NO_BUCKETS: [f64; 0] = [];
         composite_metric! {
             struct CompositeMetric {
-                #[name = "example_gauge_1"]
+                #[name = "example_hist"]
                 #[desc = "description"]
-                gauge_metric_1: Gauge,
-                #[name = "example_gauge_2"]
+                #[buckets = NO_BUCKETS]
+                #[allow(dead_code)]
+                hist: Histogram,
+            }
+        }
+
+        assert!(CompositeMetric::register(&Registry::new()).is_err());
+    }
+
+    #[test]
+    fn field_count() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "counted_counter"]
                 #[desc = "description"]
-                gauge_metric_2: Gauge,
+                counter: IntCounter,
+                #[name = "counted_gauge"]
+                #[desc = "description"]
+                gauge: IntGauge,
+                #[name = "counted_hist"]
+                #[desc = "description"]
+                hist: Histogram,
             }
         }
 
-        let reg = Registry::new();
-        let metric = CompositeMetric::register(&reg).unwrap();
-        metric.gauge_metric_1().inc();
-        metric.gauge_metric_2().inc();
+        const COUNT: usize = CompositeMetric::FIELD_COUNT;
+        assert_eq!(COUNT, 3);
+        assert_eq!(CompositeMetric::field_count(), 3);
 
-        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.counter().inc();
+        metric.gauge().inc();
+        metric.hist().observe(1.0);
+        assert_eq!((&metric).into_iter().len(), CompositeMetric::FIELD_COUNT);
 
+        composite_metric! {
+            struct WithRegistry {
+                #[registry]
+                registry: Registry,
+                #[name = "counted_registry_counter"]
+                #[desc = "description"]
+                counter: IntCounter,
+            }
+        }
+        assert_eq!(WithRegistry::FIELD_COUNT, 1);
+        let metric = WithRegistry::register(&Registry::new()).unwrap();
+        metric.counter().inc();
+        assert_eq!(metric.registry().gather().len(), 1);
+        assert_eq!((&metric).into_iter().len(), WithRegistry::FIELD_COUNT);
+    }
+
+    #[test]
+    fn scaled_default_buckets() {
+        let opts =
+            crate::Opts::new("example_hist", "description").with_scaled_default_buckets(10.0);
+        let hist = Histogram::try_from(opts).unwrap();
+        hist.observe(1.0);
+        let families = hist.collect();
+        let bounds = families[0].get_metric()[0]
+            .get_histogram()
+            .get_bucket()
+            .iter()
+            .map(|bucket| bucket.upper_bound())
+            .collect::<Vec<_>>();
         assert_eq!(
-            enc,
-            r#"# HELP example_gauge_1 description
-# TYPE example_gauge_1 gauge
-example_gauge_1 1
-# HELP example_gauge_2 description
-# TYPE example_gauge_2 gauge
-example_gauge_2 1
-"#
+            bounds,
+            [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0]
         );
     }
 
     #[test]
-    fn with_name_desc() {
+    fn scaled_default_buckets_rejects_invalid_factor() {
+        for factor in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let opts =
+                crate::Opts::new("example_hist", "description").with_scaled_default_buckets(factor);
+            let hist: prometheus::Result<Histogram> = opts.try_into();
+            assert!(hist.is_err(), "factor {factor}");
+        }
+        let opts = crate::Opts::new("example_hist", "description").with_scaled_default_buckets(0.0);
+        assert_eq!(
+            Histogram::try_from(opts).unwrap_err().to_string(),
+            "Error: invalid buckets for example_hist, buckets must be strictly increasing"
+        );
+    }
+
+    #[test]
+    fn dotted_name() {
         composite_metric! {
             struct CompositeMetric {
-                #[name = "example_gauge"]
+                #[dotted_name = "http.server.requests"]
                 #[desc = "description"]
-                gauge_metric: Gauge,
+                requests: IntCounter,
             }
         }
-        let reg = Registry::new();
-        let metric = CompositeMetric::register(&reg).unwrap();
-        metric.gauge_metric().inc();
-        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
 
-        assert_eq!(parse_name(&enc), "example_gauge");
-        assert_eq!(parse_description(&enc), "description");
-        assert_eq!(parse_type(&enc), "gauge");
+        let registry = Registry::new();
+        let metric = CompositeMetric::register(&registry).unwrap();
+        metric.requests().inc();
+        let enc = TextEncoder::new()
+            .encode_to_string(&registry.gather())
+            .unwrap();
+        assert!(enc.contains("http_server_requests 1"));
+        assert_eq!(
+            CompositeMetric::metric_catalog(),
+            [("http_server_requests", "counter")]
+        );
     }
 
     #[test]
-    fn with_labels() {
+    fn strict_accepts_known_attributes() {
         composite_metric! {
+            #[strict]
             struct CompositeMetric {
-                #[name = "example_gauge_vec"]
+                /// Requests by method.
+                #[name = "strict_requests"]
                 #[desc = "description"]
-                #[labels = ["label1", "label2"]]
-                gauge_vec_metric: GaugeVec,
+                #[cfg_attr(test, labels = ["method"])]
+                #[allow(clippy::needless_lifetimes)]
+                requests: IntCounterVec,
+                #[dotted_name = "strict.latency"]
+                #[desc = "description"]
+                #[buckets = [0.1, 1.0]]
+                latency: Histogram,
             }
         }
-        let reg = Registry::new();
-        let metric = CompositeMetric::register(&reg).unwrap();
-        metric
-            .gauge_vec_metric()
-            .with_label_values(&["a", "b"])
-            .inc();
-        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
 
-        assert_eq!(parse_name(&enc), "example_gauge_vec");
-        assert_eq!(parse_description(&enc), "description");
-        assert_eq!(parse_type(&enc), "gauge");
-        assert_eq!(parse_labels(&enc), vec!["label1", "label2"]);
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.requests().with_label_values(&["GET"]).inc();
+        metric.latency().observe(0.5);
+        assert_eq!(CompositeMetric::FIELD_COUNT, 2);
     }
 
     #[test]
-    fn with_buckets() {
+    fn from_parts() {
         composite_metric! {
             struct CompositeMetric {
-                #[name = "example_hist"]
+                #[name = "parts_counter"]
                 #[desc = "description"]
-                #[buckets = [0.1, 0.5]]
-                hist_metric: Histogram,
+                counter: IntCounter,
+                #[name = "parts_gauge"]
+                #[desc = "description"]
+                gauge: IntGauge,
             }
         }
-        let reg = Registry::new();
-        let metric = CompositeMetric::register(&reg).unwrap();
-        metric.hist_metric().observe(0.1);
-        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
 
-        assert_eq!(parse_name(&enc), "example_hist");
-        assert_eq!(parse_description(&enc), "description");
-        assert_eq!(parse_type(&enc), "histogram");
-        assert_eq!(parse_buckets(&enc), vec!["0.1", "0.5", "+Inf"]);
+        let counter = IntCounter::new("injected_counter", "description").unwrap();
+        let gauge = IntGauge::new("injected_gauge", "description").unwrap();
+        let metric = CompositeMetric::from_parts(counter.clone(), gauge.clone());
+        metric.counter().inc();
+        metric.gauge().set(3);
+        assert_eq!(counter.get(), 1);
+        assert_eq!(gauge.get(), 3);
+        assert_eq!(metric.counter_name(), "injected_counter");
+    }
+
+    #[test]
+    fn struct_cfg() {
+        composite_metric! {
+            #[cfg(any())]
+            struct ExcludedMetric {
+                #[name = "excluded_gauge"]
+                #[desc = "description"]
+                #[labels = ["label"]]
+                gauge: IntGaugeVec,
+            }
+        }
+
+        composite_metric! {
+            #[cfg(test)]
+            #[cfg_attr(test, derive(Clone))]
+            struct IncludedMetric {
+                #[name = "included_gauge"]
+                #[desc = "description"]
+                gauge: IntGauge,
+            }
+        }
+
+        let metric = IncludedMetric::register(&Registry::new()).unwrap();
+        metric.clone().gauge().set(3);
+        assert_eq!(metric.gauge().get(), 3);
+    }
+
+    #[test]
+    fn absolute_field_types() {
+        composite_metric! {
+            struct CompositeMetric {
+                #[name = "absolute_gauge"]
+                #[desc = "description"]
+                gauge: ::prometheus::IntGauge,
+                #[name = "absolute_counter"]
+                #[desc = "description"]
+                counter: ::prometheus::core::GenericCounter<::prometheus::core::AtomicU64>,
+            }
+        }
+
+        let metric = CompositeMetric::register(&Registry::new()).unwrap();
+        metric.gauge().set(3);
+        metric.counter().inc();
+        assert_eq!(
+            CompositeMetric::metric_catalog(),
+            [("absolute_gauge", "gauge"), ("absolute_counter", "counter")]
+        );
     }
 }
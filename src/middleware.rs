@@ -0,0 +1,203 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use pin_project_lite::pin_project;
+use prometheus::{Histogram, IntCounter};
+use tower_service::Service;
+
+/// The metrics recorded per request by [`MetricsLayer`].
+///
+/// Implement it for a composite metric with fields for the request count and latency, the
+/// generated accessors make this a matter of forwarding:
+///
+/// ```
+/// use prometheus::{Histogram, IntCounter};
+/// use prometheus_macros::{composite_metric, RequestMetrics};
+///
+/// composite_metric! {
+///     struct HttpMetric {
+///         #[name = "http_requests_total"]
+///         #[desc = "Number of HTTP requests"]
+///         requests: IntCounter,
+///         #[name = "http_request_duration_seconds"]
+///         #[desc = "Latency of HTTP requests"]
+///         latency: Histogram,
+///     }
+/// }
+///
+/// impl RequestMetrics for HttpMetric {
+///     fn request_count(&self) -> &IntCounter {
+///         self.requests()
+///     }
+///
+///     fn request_latency(&self) -> &Histogram {
+///         self.latency()
+///     }
+/// }
+/// ```
+pub trait RequestMetrics {
+    /// The counter incremented when a request is passed to the inner service.
+    fn request_count(&self) -> &IntCounter;
+
+    /// The histogram observing the seconds until the response future completed, either
+    /// successfully or with an error.
+    fn request_latency(&self) -> &Histogram;
+}
+
+/// Wraps services to record the [`RequestMetrics`] of every request.
+///
+/// The layer doesn't depend on `tower-layer`, so it is applied with [`MetricsLayer::layer`]
+/// directly or through `tower::layer::layer_fn`, e.g.
+/// `ServiceBuilder::new().layer(layer_fn(|svc| metrics_layer.layer(svc)))`.
+#[derive(Debug)]
+pub struct MetricsLayer<M> {
+    metrics: Arc<M>,
+}
+
+impl<M> MetricsLayer<M> {
+    /// Creates a layer recording to the given metrics.
+    pub fn new(metrics: Arc<M>) -> Self {
+        Self { metrics }
+    }
+
+    /// Wraps the service.
+    pub fn layer<S>(&self, inner: S) -> MetricsService<S, M> {
+        MetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<M> Clone for MetricsLayer<M> {
+    fn clone(&self) -> Self {
+        Self::new(self.metrics.clone())
+    }
+}
+
+/// A service recording the [`RequestMetrics`] of the requests to the inner service, see
+/// [`MetricsLayer`].
+#[derive(Debug)]
+pub struct MetricsService<S, M> {
+    inner: S,
+    metrics: Arc<M>,
+}
+
+impl<S: Clone, M> Clone for MetricsService<S, M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<S, M, Req> Service<Req> for MetricsService<S, M>
+where
+    S: Service<Req>,
+    M: RequestMetrics,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = MetricsFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.metrics.request_count().inc();
+        MetricsFuture {
+            inner: self.inner.call(req),
+            latency: self.metrics.request_latency().clone(),
+            start: Instant::now(),
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of [`MetricsService`], observing the latency on completion.
+    ///
+    /// Dropping the future before it completes discards the latency.
+    #[derive(Debug)]
+    pub struct MetricsFuture<F> {
+        #[pin]
+        inner: F,
+        latency: Histogram,
+        start: Instant,
+    }
+}
+
+impl<F: Future> Future for MetricsFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = std::task::ready!(this.inner.poll(cx));
+        this.latency.observe(this.start.elapsed().as_secs_f64());
+        Poll::Ready(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::future::{poll_fn, Ready};
+
+    struct Metrics {
+        requests: IntCounter,
+        latency: Histogram,
+    }
+
+    impl RequestMetrics for Metrics {
+        fn request_count(&self) -> &IntCounter {
+            &self.requests
+        }
+
+        fn request_latency(&self) -> &Histogram {
+            &self.latency
+        }
+    }
+
+    struct Echo;
+
+    impl Service<&'static str> for Echo {
+        type Response = &'static str;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: &'static str) -> Self::Future {
+            std::future::ready(Ok(req))
+        }
+    }
+
+    #[tokio::test]
+    async fn layer_records_requests() {
+        let metrics = Arc::new(Metrics {
+            requests: IntCounter::new("requests_total", "description").unwrap(),
+            latency: Histogram::with_opts(prometheus::HistogramOpts::new(
+                "request_duration_seconds",
+                "description",
+            ))
+            .unwrap(),
+        });
+        let mut svc = MetricsLayer::new(metrics.clone()).layer(Echo);
+
+        for req in ["a", "b"] {
+            poll_fn(|cx| svc.poll_ready(cx)).await.unwrap();
+            assert_eq!(svc.call(req).await.unwrap(), req);
+        }
+        drop(svc.call("cancelled"));
+
+        assert_eq!(metrics.requests.get(), 3);
+        assert_eq!(metrics.latency.get_sample_count(), 2);
+    }
+}
@@ -0,0 +1,163 @@
+/// A label value pattern of `#[label_patterns]`, supporting a small subset of regular
+/// expressions.
+///
+/// Patterns always match the whole value, `^` and `$` anchors are accepted but implied.
+/// Supported are literal characters, `\` escapes, `.` for any character, the `*`, `+` and `?`
+/// quantifiers, and alternations with `|`, optionally grouped in parentheses.
+#[derive(Clone, Debug)]
+pub struct LabelPattern {
+    pattern: String,
+    alternatives: Vec<Vec<Node>>,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+impl LabelPattern {
+    /// Parses the pattern.
+    ///
+    /// Panics if the pattern is malformed, as patterns are given in the metric attributes.
+    pub fn new(pattern: &str) -> Self {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        let mut pos = 0;
+        let alternatives = parse_alternatives(&chars, &mut pos, false)
+            .unwrap_or_else(|err| panic!("invalid label pattern {pattern:?}: {err}"));
+        Self {
+            pattern: pattern.to_owned(),
+            alternatives,
+        }
+    }
+
+    /// Returns the pattern as given.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns whether the whole value matches the pattern.
+    pub fn matches(&self, value: &str) -> bool {
+        let chars = value.chars().collect::<Vec<_>>();
+        match_alternatives(&self.alternatives, &chars, 0, &|pos| pos == chars.len())
+    }
+}
+
+fn parse_alternatives(
+    chars: &[char],
+    pos: &mut usize,
+    nested: bool,
+) -> Result<Vec<Vec<Node>>, &'static str> {
+    let mut alternatives = vec![Vec::new()];
+    while let Some(&c) = chars.get(*pos) {
+        *pos += 1;
+        let seq = alternatives.last_mut().expect("one alternative");
+        match c {
+            '|' => alternatives.push(Vec::new()),
+            '(' => seq.push(Node::Group(parse_alternatives(chars, pos, true)?)),
+            ')' if nested => return Ok(alternatives),
+            ')' => return Err("unmatched `)`"),
+            '*' | '+' | '?' => {
+                let node = seq.pop().ok_or("quantifier without operand")?;
+                let (min, max) = match c {
+                    '*' => (0, None),
+                    '+' => (1, None),
+                    _ => (0, Some(1)),
+                };
+                seq.push(Node::Repeat(Box::new(node), min, max));
+            }
+            '^' if *pos == 1 => {}
+            '$' if *pos == chars.len() => {}
+            '.' => seq.push(Node::Any),
+            '\\' => {
+                let escaped = chars.get(*pos).ok_or("trailing `\\`")?;
+                *pos += 1;
+                seq.push(Node::Char(*escaped));
+            }
+            c => seq.push(Node::Char(c)),
+        }
+    }
+    if nested {
+        Err("unclosed `(`")
+    } else {
+        Ok(alternatives)
+    }
+}
+
+fn match_alternatives(
+    alternatives: &[Vec<Node>],
+    chars: &[char],
+    pos: usize,
+    then: &dyn Fn(usize) -> bool,
+) -> bool {
+    alternatives
+        .iter()
+        .any(|seq| match_seq(seq, chars, pos, then))
+}
+
+fn match_seq(seq: &[Node], chars: &[char], pos: usize, then: &dyn Fn(usize) -> bool) -> bool {
+    match seq.split_first() {
+        None => then(pos),
+        Some((node, rest)) => {
+            match_node(node, chars, pos, &|pos| match_seq(rest, chars, pos, then))
+        }
+    }
+}
+
+fn match_node(node: &Node, chars: &[char], pos: usize, then: &dyn Fn(usize) -> bool) -> bool {
+    match node {
+        Node::Char(c) => chars.get(pos) == Some(c) && then(pos + 1),
+        Node::Any => pos < chars.len() && then(pos + 1),
+        Node::Group(alternatives) => match_alternatives(alternatives, chars, pos, then),
+        Node::Repeat(node, min, max) => match_repeat(node, *min, *max, chars, pos, then),
+    }
+}
+
+/// Matches `node` greedily between `min` and `max` times, counting down on each repetition.
+fn match_repeat(
+    node: &Node,
+    min: usize,
+    max: Option<usize>,
+    chars: &[char],
+    pos: usize,
+    then: &dyn Fn(usize) -> bool,
+) -> bool {
+    let more = max != Some(0)
+        && match_node(node, chars, pos, &|next| {
+            // Empty repetitions beyond the minimum can't make progress, stop to avoid endless
+            // recursion.
+            (next != pos || min > 0)
+                && match_repeat(
+                    node,
+                    min.saturating_sub(1),
+                    max.map(|max| max - 1),
+                    chars,
+                    next,
+                    then,
+                )
+        });
+    more || (min == 0 && then(pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subset() {
+        let method = LabelPattern::new("^(GET|POST|PUT)$");
+        assert!(method.matches("GET"));
+        assert!(method.matches("PUT"));
+        assert!(!method.matches("GETS"));
+        assert!(!method.matches("DELETE"));
+
+        assert!(LabelPattern::new(".*").matches(""));
+        assert!(LabelPattern::new("/api/.+").matches("/api/users"));
+        assert!(!LabelPattern::new("/api/.+").matches("/api/"));
+        assert!(LabelPattern::new("v1\\.2x?").matches("v1.2"));
+        assert!(LabelPattern::new("(a|b)*c").matches("abbac"));
+        assert!(LabelPattern::new("(a|)+").matches(""));
+    }
+}
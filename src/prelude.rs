@@ -0,0 +1,35 @@
+//! Re-exports of the macros and the commonly used metric types.
+//!
+//! The metric types are the ones of [`prometheus`], so they don't conflict with direct
+//! imports from `prometheus`. [`Opts`] is this crate's options type though: an explicit
+//! `use prometheus::Opts` takes precedence over the prelude, while glob importing both
+//! `prometheus::*` and the prelude makes `Opts` ambiguous.
+//!
+//! ```
+//! use prometheus_macros::prelude::*;
+//!
+//! composite_metric! {
+//!     struct CompositeMetric {
+//!         #[name = "custom_counter"]
+//!         #[desc = "Example counter"]
+//!         custom_counter: IntCounter,
+//!         #[name = "custom_hist_vec"]
+//!         #[desc = "Example histogram vec"]
+//!         #[labels = ["path"]]
+//!         custom_hist_vec: HistogramVec,
+//!     }
+//! }
+//!
+//! let metric = CompositeMetric::register(&Registry::new()).unwrap();
+//! metric.custom_counter().inc();
+//! let gauge: Gauge = Opts::new("custom_gauge", "Example gauge").try_into().unwrap();
+//! gauge.set(1.0);
+//! ```
+
+#[cfg(feature = "derive")]
+pub use crate::CompositeMetric;
+pub use crate::{composite_metric, Opts, OptsTemplate, Registrar, RegistryLike};
+pub use prometheus::{
+    Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, Registry,
+};
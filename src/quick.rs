@@ -0,0 +1,86 @@
+/// Constructs an [`IntCounter`](prometheus::IntCounter) and registers it to the default
+/// registry.
+///
+/// This suits one-off metrics outside of a composite struct. Registering the same name
+/// twice returns an error, so the handle should be stored, e.g. in a `LazyLock`.
+///
+/// ```
+/// use prometheus_macros::quick_counter;
+///
+/// let calls = quick_counter!("quick_calls", "Number of calls").unwrap();
+/// calls.inc();
+/// ```
+#[macro_export]
+macro_rules! quick_counter {
+    ($name:expr, $desc:expr $(,)?) => {
+        $crate::__private::register_default::<::prometheus::IntCounter>($crate::Opts::new(
+            $name, $desc,
+        ))
+    };
+}
+
+/// Constructs a [`Gauge`](prometheus::Gauge) and registers it to the default registry.
+///
+/// See [`quick_counter!`] for details.
+///
+/// ```
+/// use prometheus_macros::quick_gauge;
+///
+/// let temperature = quick_gauge!("quick_temperature", "Current temperature").unwrap();
+/// temperature.set(21.5);
+/// ```
+#[macro_export]
+macro_rules! quick_gauge {
+    ($name:expr, $desc:expr $(,)?) => {
+        $crate::__private::register_default::<::prometheus::Gauge>($crate::Opts::new($name, $desc))
+    };
+}
+
+/// Constructs a [`Histogram`](prometheus::Histogram) with optional buckets and registers it
+/// to the default registry.
+///
+/// See [`quick_counter!`] for details.
+///
+/// ```
+/// use prometheus_macros::quick_histogram;
+///
+/// let latency = quick_histogram!("quick_latency", "Latency", [0.1, 0.5, 1.0]).unwrap();
+/// latency.observe(0.2);
+/// ```
+#[macro_export]
+macro_rules! quick_histogram {
+    ($name:expr, $desc:expr $(,)?) => {
+        $crate::__private::register_default::<::prometheus::Histogram>($crate::Opts::new(
+            $name, $desc,
+        ))
+    };
+    ($name:expr, $desc:expr, $buckets:expr $(,)?) => {
+        $crate::__private::register_default::<::prometheus::Histogram>(
+            $crate::Opts::new($name, $desc).with_buckets(&$buckets),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::core::Collector;
+
+    #[test]
+    fn quick_metrics() {
+        let counter = quick_counter!("quick_test_counter", "description").unwrap();
+        counter.inc();
+        let gauge = quick_gauge!("quick_test_gauge", "description").unwrap();
+        gauge.set(1.0);
+        let hist = quick_histogram!("quick_test_hist", "description", [0.5, 1.0]).unwrap();
+        hist.observe(0.7);
+
+        let names = prometheus::gather()
+            .into_iter()
+            .map(|f| f.name().to_owned())
+            .collect::<Vec<_>>();
+        for metric in [counter.desc(), gauge.desc(), hist.desc()] {
+            assert!(names.contains(&metric[0].fq_name));
+        }
+        assert!(quick_counter!("quick_test_counter", "description").is_err());
+    }
+}
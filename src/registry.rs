@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::Registry;
+
+/// A collector that only describes metrics, used to unregister collectors by descriptor.
+struct DescCollector(Vec<Desc>);
+
+impl Collector for DescCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.0.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        Vec::new()
+    }
+}
+
+/// Label pairs that have the same value across all series of a family.
+fn common_labels(family: &MetricFamily) -> HashMap<String, String> {
+    let mut metrics = family.get_metric().iter();
+    let mut labels: HashMap<_, _> = metrics
+        .next()
+        .map(|m| {
+            m.get_label()
+                .iter()
+                .map(|l| (l.name().to_owned(), l.value().to_owned()))
+                .collect()
+        })
+        .unwrap_or_default();
+    for metric in metrics {
+        labels.retain(|name, value| {
+            metric
+                .get_label()
+                .iter()
+                .any(|l| l.name() == name && l.value() == value)
+        });
+    }
+    labels
+}
+
+fn unregister_family(
+    registry: &Registry,
+    family: &MetricFamily,
+    const_labels: HashMap<String, String>,
+) -> bool {
+    Desc::new(
+        family.name().to_owned(),
+        family.help().to_owned(),
+        Vec::new(),
+        const_labels,
+    )
+    .and_then(|desc| registry.unregister(Box::new(DescCollector(vec![desc]))))
+    .is_ok()
+}
+
+/// Unregisters all collectors from the registry on a best-effort basis.
+///
+/// Prometheus offers no way to list registered collectors, so the descriptors are
+/// reconstructed from the gathered metric families. This covers collectors describing a
+/// single metric, with or without const labels. Collectors that are currently not exposed,
+/// such as vector metrics without any series, as well as collectors describing multiple
+/// metrics remain registered.
+///
+/// Returns the number of unregistered collectors.
+///
+/// ```
+/// use prometheus::{IntGauge, Registry};
+///
+/// let registry = Registry::new();
+/// let gauge = IntGauge::new("example_gauge", "description").unwrap();
+/// registry.register(Box::new(gauge.clone())).unwrap();
+///
+/// assert_eq!(prometheus_macros::clear_registry(&registry), 1);
+/// assert!(registry.gather().is_empty());
+/// // the metric can be registered again
+/// registry.register(Box::new(gauge)).unwrap();
+/// ```
+pub fn clear_registry(registry: &Registry) -> usize {
+    registry
+        .gather()
+        .iter()
+        .filter(|family| {
+            unregister_family(registry, family, HashMap::new())
+                || unregister_family(registry, family, common_labels(family))
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{CounterVec, Gauge, Histogram, HistogramOpts, IntCounter, Opts};
+
+    #[test]
+    fn clear_registered_metrics() {
+        let reg = Registry::new();
+        let gauge = Gauge::new("example_gauge", "description").unwrap();
+        let counter = IntCounter::with_opts(
+            Opts::new("example_counter", "description").const_label("service", "a"),
+        )
+        .unwrap();
+        let hist = Histogram::with_opts(HistogramOpts::new("example_hist", "description")).unwrap();
+        let vec = CounterVec::new(Opts::new("example_vec", "description"), &["label"]).unwrap();
+        vec.with_label_values(&["a"]).inc();
+        vec.with_label_values(&["b"]).inc();
+        for c in [
+            Box::new(gauge.clone()) as Box<dyn Collector>,
+            Box::new(counter.clone()),
+            Box::new(hist.clone()),
+            Box::new(vec.clone()),
+        ] {
+            reg.register(c).unwrap();
+        }
+        assert_eq!(reg.gather().len(), 4);
+
+        assert_eq!(clear_registry(&reg), 4);
+        assert!(reg.gather().is_empty());
+
+        reg.register(Box::new(gauge)).unwrap();
+        reg.register(Box::new(counter)).unwrap();
+    }
+}
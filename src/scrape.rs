@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use ::hyper::header::{ALLOW, CONTENT_TYPE};
+use ::hyper::{Body, Method, Request, Response, StatusCode};
+use prometheus::{Encoder, Registry, TextEncoder};
+
+/// Serves the metrics gathered from `registry` in the text exposition format, with the
+/// [`TEXT_CONTENT_TYPE`](crate::TEXT_CONTENT_TYPE).
+///
+/// Any method other than `GET` is rejected with `405 Method Not Allowed`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::convert::Infallible;
+/// use std::sync::Arc;
+///
+/// use hyper::service::{make_service_fn, service_fn};
+/// use hyper::Server;
+/// use prometheus::Registry;
+///
+/// # async fn serve() -> hyper::Result<()> {
+/// let registry = Arc::new(Registry::new());
+/// let make_svc = make_service_fn(move |_| {
+///     let registry = registry.clone();
+///     async move {
+///         Ok::<_, Infallible>(service_fn(move |req| {
+///             let registry = registry.clone();
+///             async move { Ok::<_, Infallible>(prometheus_macros::scrape_handler(registry, req)) }
+///         }))
+///     }
+/// });
+/// Server::bind(&([127, 0, 0, 1], 9090).into()).serve(make_svc).await
+/// # }
+/// ```
+pub fn scrape_handler(registry: Arc<Registry>, req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET {
+        return Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(ALLOW, "GET")
+            .body(Body::empty())
+            .expect("valid response");
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buf = Vec::new();
+    match encoder.encode(&registry.gather(), &mut buf) {
+        Ok(()) => Response::builder()
+            .header(CONTENT_TYPE, crate::TEXT_CONTENT_TYPE)
+            .body(Body::from(buf))
+            .expect("valid response"),
+        Err(err) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(err.to_string()))
+            .expect("valid response"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::IntGauge;
+
+    #[tokio::test]
+    async fn scrape_get_and_post() {
+        let registry = Arc::new(Registry::new());
+        let gauge = IntGauge::new("example_gauge", "description").unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        gauge.set(3);
+
+        let req = Request::get("/metrics").body(Body::empty()).unwrap();
+        let res = scrape_handler(registry.clone(), req);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[CONTENT_TYPE], crate::TEXT_CONTENT_TYPE);
+        let body = ::hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(std::str::from_utf8(&body)
+            .unwrap()
+            .contains("example_gauge 3"));
+
+        let req = Request::post("/metrics").body(Body::empty()).unwrap();
+        let res = scrape_handler(registry, req);
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[ALLOW], "GET");
+    }
+}
@@ -0,0 +1,249 @@
+use prometheus::core::Collector;
+use prometheus::proto::MetricType;
+use prometheus::{Histogram, HistogramOpts};
+
+use crate::RegistryLike;
+
+/// Replaces a histogram with a fresh one of the same options, e.g. between test cases.
+///
+/// Prometheus histograms can't be reset, so the histogram is re-created with the name, help,
+/// const labels and buckets of the old one, which is unregistered from the registry in favor
+/// of the new one. Clones of the old histogram keep observing into the unregistered one.
+///
+/// The `<field>_reset` methods generated for `Histogram` fields call this with the field.
+///
+/// ```
+/// use prometheus::{Histogram, Registry};
+/// use prometheus_macros::composite_metric;
+///
+/// composite_metric! {
+///     struct CompositeMetric {
+///         #[name = "latency"]
+///         #[desc = "Request latency"]
+///         latency: Histogram,
+///     }
+/// }
+///
+/// let registry = Registry::new();
+/// let mut metric = CompositeMetric::register(&registry).unwrap();
+/// metric.latency().observe(0.1);
+/// metric.latency_reset(&registry).unwrap();
+/// assert_eq!(metric.latency().get_sample_count(), 0);
+/// ```
+pub fn reset_histogram(
+    histogram: &mut Histogram,
+    registry: &impl RegistryLike,
+) -> prometheus::Result<()> {
+    let desc = histogram.desc()[0];
+    let buckets = histogram.collect()[0].get_metric()[0]
+        .get_histogram()
+        .get_bucket()
+        .iter()
+        .map(|bucket| bucket.upper_bound())
+        .collect();
+    let opts = HistogramOpts::new(desc.fq_name.clone(), desc.help.clone())
+        .const_labels(
+            desc.const_label_pairs
+                .iter()
+                .map(|pair| (pair.name().to_owned(), pair.value().to_owned()))
+                .collect(),
+        )
+        .buckets(buckets);
+    let fresh = Histogram::with_opts(opts)?;
+
+    let registry = registry.as_registry();
+    registry.unregister(Box::new(histogram.clone()))?;
+    registry.register(Box::new(fresh.clone()))?;
+    *histogram = fresh;
+    Ok(())
+}
+
+/// Asserts that a registry exposes a metric with a single series of the given value.
+///
+/// The metric is looked up by its full name among the gathered families, and must be a
+/// counter or a gauge. On a mismatch, the message lists the available metric names.
+///
+/// ```
+/// use prometheus::{IntCounter, Registry};
+/// use prometheus_macros::assert_metric;
+///
+/// let registry = Registry::new();
+/// let counter = IntCounter::new("http_requests_total", "Requests").unwrap();
+/// registry.register(Box::new(counter.clone())).unwrap();
+/// counter.inc_by(5);
+///
+/// assert_metric!(registry, "http_requests_total", 5.0);
+/// ```
+#[macro_export]
+macro_rules! assert_metric {
+    ($registry:expr, $name:expr, $value:expr $(,)?) => {
+        match $crate::__private::metric_value(&$registry, $name) {
+            ::std::result::Result::Ok(value) => ::std::assert_eq!(
+                value,
+                $value,
+                "metric {:?} has value {}, expected {}",
+                $name,
+                value,
+                $value,
+            ),
+            ::std::result::Result::Err(msg) => ::std::panic!("{}", msg),
+        }
+    };
+}
+
+/// Returns the value of the single series of a counter or gauge, used by [`assert_metric!`].
+pub fn metric_value(registry: &impl RegistryLike, name: &str) -> Result<f64, String> {
+    let families = registry.as_registry().gather();
+    let Some(family) = families.iter().find(|family| family.name() == name) else {
+        let names = families
+            .iter()
+            .map(|family| family.name())
+            .collect::<Vec<_>>();
+        return Err(format!(
+            "metric {name:?} not found, available metrics: {names:?}"
+        ));
+    };
+    let [metric] = family.get_metric() else {
+        return Err(format!(
+            "metric {name:?} has {} series, expected a single one",
+            family.get_metric().len()
+        ));
+    };
+    match family.get_field_type() {
+        MetricType::COUNTER => Ok(metric.get_counter().value()),
+        MetricType::GAUGE => Ok(metric.get_gauge().value()),
+        kind => Err(format!(
+            "metric {name:?} is a {kind:?}, expected a counter or gauge"
+        )),
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __composite_metric_test_util {
+    (@histogram_methods $v:vis, $metric_name:ident) => {
+        $crate::__private::paste! {
+            #[doc = concat!("Replaces `", stringify!($metric_name), "` with a fresh histogram, see `reset_histogram`.")]
+            #[allow(dead_code)]
+            $v fn [<$metric_name _reset>](
+                &mut self,
+                registry: &impl $crate::RegistryLike,
+            ) -> ::prometheus::Result<()> {
+                $crate::reset_histogram(&mut self.$metric_name, registry)
+            }
+        }
+    };
+}
+
+/// Asserts that a registry exposes the expected number of metric families, e.g. to catch
+/// metrics added by accident.
+///
+/// On a mismatch, the message lists the gathered metric names. Vector metrics without any
+/// series are not gathered, so they only count once a series exists.
+///
+/// ```
+/// use prometheus::{IntCounter, IntGauge, Registry};
+/// use prometheus_macros::assert_registry_metric_count;
+///
+/// let registry = Registry::new();
+/// registry.register(Box::new(IntCounter::new("requests", "Requests").unwrap())).unwrap();
+/// registry.register(Box::new(IntGauge::new("connections", "Connections").unwrap())).unwrap();
+///
+/// assert_registry_metric_count(&registry, 2);
+/// ```
+#[track_caller]
+pub fn assert_registry_metric_count(registry: &impl RegistryLike, expected: usize) {
+    let families = registry.as_registry().gather();
+    let names = families
+        .iter()
+        .map(|family| family.name())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names.len(),
+        expected,
+        "registry has {} metrics, expected {expected}: {names:?}",
+        names.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn reset_keeps_opts() {
+        let registry = Registry::new();
+        let mut hist = Histogram::with_opts(
+            HistogramOpts::new("example_hist", "description")
+                .const_labels([("service".to_owned(), "api".to_owned())].into())
+                .buckets(vec![0.5, 1.0]),
+        )
+        .unwrap();
+        registry.register(Box::new(hist.clone())).unwrap();
+        hist.observe(0.7);
+
+        reset_histogram(&mut hist, &registry).unwrap();
+        assert_eq!(hist.get_sample_count(), 0);
+        hist.observe(0.2);
+
+        let families = registry.gather();
+        assert_eq!(families.len(), 1);
+        let metric = &families[0].get_metric()[0];
+        assert_eq!(metric.get_label()[0].value(), "api");
+        let histogram = metric.get_histogram();
+        assert_eq!(histogram.get_sample_count(), 1);
+        let bounds: Vec<_> = histogram
+            .get_bucket()
+            .iter()
+            .map(|b| b.upper_bound())
+            .collect();
+        assert_eq!(bounds, [0.5, 1.0]);
+    }
+
+    #[test]
+    fn assert_metric_value() {
+        let registry = Registry::new();
+        let gauge = prometheus::Gauge::new("example_gauge", "description").unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        gauge.set(2.5);
+
+        assert_metric!(registry, "example_gauge", 2.5);
+        assert_metric!(&registry, "example_gauge", 2.5);
+        let err = metric_value(&registry, "example_missing").unwrap_err();
+        assert_eq!(
+            err,
+            "metric \"example_missing\" not found, available metrics: [\"example_gauge\"]"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "metric \"example_counter\" has value 1, expected 2")]
+    fn assert_metric_mismatch() {
+        let registry = Registry::new();
+        let counter = prometheus::IntCounter::new("example_counter", "description").unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        counter.inc();
+
+        assert_metric!(registry, "example_counter", 2.0);
+    }
+
+    #[test]
+    fn registry_metric_count() {
+        let registry = Registry::new();
+        let gauge = prometheus::IntGauge::new("example_gauge", "description").unwrap();
+        let counter = prometheus::IntCounter::new("example_counter", "description").unwrap();
+        registry.register(Box::new(gauge)).unwrap();
+        registry.register(Box::new(counter)).unwrap();
+        assert_registry_metric_count(&registry, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "registry has 1 metrics, expected 0: [\"example_gauge\"]")]
+    fn registry_metric_count_mismatch() {
+        let registry = Registry::new();
+        let gauge = prometheus::IntGauge::new("example_gauge", "description").unwrap();
+        registry.register(Box::new(gauge)).unwrap();
+        assert_registry_metric_count(&registry, 0);
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::Gauge;
+
+use crate::Opts;
+
+/// A gauge that carries an explicit sample timestamp, e.g. for backfilling historical data.
+///
+/// The timestamp is attached to the exposed sample and emitted by encoders that support
+/// client-side timestamps, such as the text and protobuf encoders. Note that the Prometheus
+/// server only honors such timestamps within its configured limits (`honor_timestamps`).
+///
+/// ```
+/// use prometheus::{Registry, TextEncoder};
+/// use prometheus_macros::{composite_metric, TimestampedGauge};
+///
+/// composite_metric! {
+///     struct BackfillMetric {
+///         #[name = "backfilled_value"]
+///         #[desc = "Example timestamped gauge"]
+///         value: TimestampedGauge,
+///     }
+/// }
+///
+/// let registry = Registry::new();
+/// let metric = BackfillMetric::register(&registry).unwrap();
+/// metric.value().set_with_timestamp(42.0, 1_700_000_000_000);
+///
+/// let enc = TextEncoder::new().encode_to_string(&registry.gather()).unwrap();
+/// assert!(enc.contains("backfilled_value 42 1700000000000"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct TimestampedGauge {
+    gauge: Gauge,
+    timestamp_ms: Arc<Mutex<i64>>,
+}
+
+impl TimestampedGauge {
+    /// Sets the gauge without a timestamp, which clears a previously set timestamp.
+    pub fn set(&self, v: f64) {
+        self.set_with_timestamp(v, 0);
+    }
+
+    /// Sets the gauge with a timestamp given in milliseconds since the UNIX epoch.
+    pub fn set_with_timestamp(&self, v: f64, timestamp_ms: i64) {
+        let mut ts = self.timestamp_ms.lock().expect("lock poisoned");
+        *ts = timestamp_ms;
+        self.gauge.set(v);
+    }
+
+    /// Sets the gauge with the given time as timestamp.
+    pub fn set_at(&self, v: f64, time: SystemTime) {
+        let timestamp_ms = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        self.set_with_timestamp(v, timestamp_ms);
+    }
+
+    /// Returns the current value of the gauge.
+    pub fn get(&self) -> f64 {
+        self.gauge.get()
+    }
+
+    /// Returns the current timestamp in milliseconds, if any is set.
+    pub fn timestamp_ms(&self) -> Option<i64> {
+        let ts = *self.timestamp_ms.lock().expect("lock poisoned");
+        (ts != 0).then_some(ts)
+    }
+}
+
+impl Collector for TimestampedGauge {
+    fn desc(&self) -> Vec<&Desc> {
+        self.gauge.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let ts = self.timestamp_ms.lock().expect("lock poisoned");
+        let mut families = self.gauge.collect();
+        if *ts != 0 {
+            for metric in families.iter_mut().flat_map(|f| f.mut_metric()) {
+                metric.set_timestamp_ms(*ts);
+            }
+        }
+        families
+    }
+}
+
+impl TryFrom<Opts<'_>> for TimestampedGauge {
+    type Error = prometheus::Error;
+    fn try_from(opts: Opts<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            gauge: opts.try_into()?,
+            timestamp_ms: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{Registry, TextEncoder};
+
+    #[test]
+    fn set_with_timestamp() {
+        let gauge: TimestampedGauge = Opts::new("example_gauge", "description")
+            .try_into()
+            .unwrap();
+        let reg = Registry::new();
+        reg.register(Box::new(gauge.clone())).unwrap();
+
+        gauge.set_with_timestamp(1.5, 1_600_000_000_000);
+        assert_eq!(gauge.timestamp_ms(), Some(1_600_000_000_000));
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+        assert_eq!(enc.lines().nth(2), Some("example_gauge 1.5 1600000000000"));
+
+        gauge.set(2.0);
+        assert_eq!(gauge.timestamp_ms(), None);
+        let enc = TextEncoder::new().encode_to_string(&reg.gather()).unwrap();
+        assert_eq!(enc.lines().nth(2), Some("example_gauge 2"));
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "derive")]
+
+use std::sync::Mutex;
+
+use prometheus::core::Collector;
+use prometheus::{Gauge, GaugeVec, Histogram, Registry, TextEncoder};
+use prometheus_macros::{composite_metric, CompositeMetric, Registrar};
+
+#[derive(CompositeMetric)]
+struct DerivedMetric {
+    #[metric(name = "example_gauge", desc = "description")]
+    gauge_metric: Gauge,
+    #[metric(name = "example_gauge_vec", desc = "description", labels = ["label1", "label2"])]
+    gauge_vec_metric: GaugeVec,
+    #[metric(name = "example_hist", desc = "description", buckets = [0.1, 0.5])]
+    hist_metric: Histogram,
+}
+
+composite_metric! {
+    struct DeclaredMetric {
+        #[name = "example_gauge"]
+        #[desc = "description"]
+        gauge_metric: Gauge,
+        #[name = "example_gauge_vec"]
+        #[desc = "description"]
+        #[labels = ["label1", "label2"]]
+        gauge_vec_metric: GaugeVec,
+        #[name = "example_hist"]
+        #[desc = "description"]
+        #[buckets = [0.1, 0.5]]
+        hist_metric: Histogram,
+    }
+}
+
+#[test]
+fn derive_matches_composite_metric() {
+    let derived_reg = Registry::new();
+    let derived = DerivedMetric::register(&derived_reg).unwrap();
+    derived.gauge_metric().inc();
+    derived
+        .gauge_vec_metric()
+        .with_label_values(&["a", "b"])
+        .inc();
+    derived.hist_metric().observe(0.2);
+
+    let declared_reg = Registry::new();
+    let declared = DeclaredMetric::register(&declared_reg).unwrap();
+    declared.gauge_metric().inc();
+    declared
+        .gauge_vec_metric()
+        .with_label_values(&["a", "b"])
+        .inc();
+    declared.hist_metric().observe(0.2);
+
+    let encoder = TextEncoder::new();
+    assert_eq!(
+        encoder.encode_to_string(&derived_reg.gather()).unwrap(),
+        encoder.encode_to_string(&declared_reg.gather()).unwrap(),
+    );
+}
+
+#[test]
+fn derive_vec_without_labels_fails() {
+    #[derive(CompositeMetric)]
+    struct InvalidMetric {
+        #[metric(name = "example_gauge_vec", desc = "description")]
+        _gauge_vec_metric: GaugeVec,
+    }
+
+    assert!(InvalidMetric::register(&Registry::new()).is_err());
+}
+
+#[test]
+fn derive_registers_to_registrar() {
+    #[derive(Default)]
+    struct MockRegistrar(Mutex<Vec<String>>);
+
+    impl Registrar for MockRegistrar {
+        fn register_collector(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+            let mut names = self.0.lock().unwrap();
+            names.extend(collector.desc().iter().map(|desc| desc.fq_name.clone()));
+            Ok(())
+        }
+    }
+
+    let registrar = MockRegistrar::default();
+    let derived = DerivedMetric::register(&registrar).unwrap();
+    derived.gauge_metric().inc();
+    assert_eq!(
+        *registrar.0.lock().unwrap(),
+        ["example_gauge", "example_gauge_vec", "example_hist"]
+    );
+}